@@ -1,30 +1,153 @@
-use std::{collections::HashMap, num::NonZeroU32, rc::Rc};
+use std::{collections::HashMap, num::NonZeroU32, rc::Rc, str::FromStr};
 
 use fontdue::{layout::GlyphRasterConfig, Font, Metrics};
-use softbuffer::Surface;
+use lazy_static::lazy_static;
+use softbuffer::{Context, Surface};
+use unicode_width::UnicodeWidthChar;
 use winit::window::Window;
 
-use crate::text_area::{BoundingBox, Coordinates, TextArea};
+use crate::{
+    text_area::{self, grapheme_width, BoundingBox, Coordinates, TextArea},
+    theme::Theme,
+};
 
 type FontAtlas = HashMap<GlyphRasterConfig, (Metrics, Vec<u8>)>;
 
+lazy_static! {
+    static ref SRGB_TO_LINEAR: [f32; 256] = {
+        let mut table = [0.0; 256];
+
+        for (i, v) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+
+            *v = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+
+        table
+    };
+}
+
+fn linear_to_srgb(v: f32) -> u8 {
+    let c = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseColorError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn from_pixel(pixel: u32) -> Self {
+        let [_, r, g, b] = pixel.to_be_bytes();
+
+        Self { r, g, b }
+    }
+
+    pub fn to_pixel(self) -> u32 {
+        u32::from_be_bytes([0, self.r, self.g, self.b])
+    }
+
+    /// Composites `self` over `background` using `alpha` as the coverage of `self`.
+    ///
+    /// When `gamma_correct` is set, the interpolation happens in linear light instead of directly
+    /// on the sRGB encoded values, which keeps thin strokes from looking too dark or too light.
+    pub fn blend_over(self, background: Color, alpha: u8, gamma_correct: bool) -> Color {
+        match alpha {
+            0 => background,
+            255 => self,
+            _ if gamma_correct => {
+                let a = alpha as f32 / 255.0;
+                let mix = |f: u8, b: u8| {
+                    linear_to_srgb(
+                        SRGB_TO_LINEAR[f as usize] * a + SRGB_TO_LINEAR[b as usize] * (1.0 - a),
+                    )
+                };
+
+                Color::new(
+                    mix(self.r, background.r),
+                    mix(self.g, background.g),
+                    mix(self.b, background.b),
+                )
+            }
+            _ => {
+                let a = alpha as u32;
+                let mix = |f: u8, b: u8| ((f as u32 * a + b as u32 * (255 - a) + 127) / 255) as u8;
+
+                Color::new(
+                    mix(self.r, background.r),
+                    mix(self.g, background.g),
+                    mix(self.b, background.b),
+                )
+            }
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    /// Parses a colour written as `#rrggbb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(ParseColorError);
+        }
+
+        let channel =
+            |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| ParseColorError);
+
+        Ok(Color::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
 struct FontManager {
     font: Font,
     atlas: FontAtlas,
+    /// The font size in logical points, independent of the display density.
     font_size: f32,
+    scale_factor: f64,
 }
 
 impl FontManager {
-    pub fn new(font: Font, font_size: f32) -> Self {
+    pub fn new(font: Font, font_size: f32, scale_factor: f64) -> Self {
         Self {
             font,
             atlas: HashMap::new(),
             font_size,
+            scale_factor,
         }
     }
 
+    /// The font size in physical pixels, as used for rasterization.
+    pub fn px(&self) -> f32 {
+        (self.font_size as f64 * self.scale_factor) as f32
+    }
+
     pub fn character_height(&self) -> usize {
-        self.font_size as usize
+        self.px() as usize
     }
 
     pub fn character_width(&self) -> usize {
@@ -35,33 +158,40 @@ impl FontManager {
     fn config_of(&self, c: char) -> GlyphRasterConfig {
         GlyphRasterConfig {
             glyph_index: self.font.lookup_glyph_index(c),
-            px: self.font_size,
+            px: self.px(),
             font_hash: self.font.file_hash(),
         }
     }
 
     pub fn rasterize(&mut self, c: char) -> &(Metrics, Vec<u8>) {
+        let px = self.px();
+
         self.atlas
             .entry(self.config_of(c))
-            .or_insert(self.font.rasterize(c, self.font_size))
+            .or_insert_with(|| self.font.rasterize(c, px))
     }
 }
 
 struct FrameBuffer {
     width: usize,
     height: usize,
+    gamma_correct: bool,
 }
 
 impl FrameBuffer {
-    pub fn new(width: usize, height: usize) -> Self {
-        Self { width, height }
+    pub fn new(width: usize, height: usize, gamma_correct: bool) -> Self {
+        Self {
+            width,
+            height,
+            gamma_correct,
+        }
     }
 
     fn lines<'a, B>(
         &'a self,
         bounding_box: B,
         buffer: &'a mut [u32],
-    ) -> impl Iterator<Item = &'a mut [u32]> + '_
+    ) -> impl Iterator<Item = &'a mut [u32]> + 'a
     where
         B: Into<BoundingBox>,
     {
@@ -78,8 +208,14 @@ impl FrameBuffer {
             })
     }
 
-    pub fn draw<I, C>(&mut self, chars: I, font: &mut FontManager, top_left: C, buffer: &mut [u32])
-    where
+    pub fn draw<I, C>(
+        &mut self,
+        chars: I,
+        font: &mut FontManager,
+        top_left: C,
+        foreground: Color,
+        buffer: &mut [u32],
+    ) where
         I: IntoIterator<Item = (Coordinates, char)>,
         C: Into<Coordinates>,
     {
@@ -96,7 +232,13 @@ impl FrameBuffer {
 
                 character_top_left.y +=
                     char_height as isize - metrics.ymin as isize - metrics.height as isize;
-                character_top_left.x += metrics.xmin as isize;
+
+                // Combining marks are centered over the character they apply to, since their
+                // bearing assumes they follow it.
+                character_top_left.x += match c.width() {
+                    Some(0) => (char_width as isize - metrics.width as isize) / 2,
+                    _ => metrics.xmin as isize,
+                };
 
                 let bounded_top_left = character_top_left.max(0);
                 let (displacement_width, displacement_height) =
@@ -107,7 +249,7 @@ impl FrameBuffer {
                     .min(self.width - bounded_top_left.x.max(0).unsigned_abs());
                 let displayed_height = metrics
                     .height
-                    .min(self.width - bounded_top_left.y.max(0).unsigned_abs());
+                    .min(self.height - bounded_top_left.y.max(0).unsigned_abs());
 
                 for (dest_line, src_line) in self
                     .lines(
@@ -125,22 +267,14 @@ impl FrameBuffer {
                             .take(displayed_height),
                     )
                 {
-                    let src = src_line[displacement_width
-                        ..displacement_width + (displayed_width - displacement_width)]
-                        .iter()
-                        .map(|v| u32::from_be_bytes([0, *v, *v, *v]))
-                        .collect::<Vec<_>>();
-
-                    for (dest, src) in dest_line.iter_mut().zip(src) {
-                        let mut d = dest.to_be_bytes();
-                        let s = src.to_be_bytes();
-
-                        if d != s {
-                            d[1] = d[1].saturating_add(s[1]);
-                            d[2] = d[2].saturating_add(s[2]);
-                            d[3] = d[3].saturating_add(s[3]);
-
-                            *dest = u32::from_be_bytes(d);
+                    let coverage = &src_line[displacement_width
+                        ..displacement_width + (displayed_width - displacement_width)];
+
+                    for (dest, alpha) in dest_line.iter_mut().zip(coverage) {
+                        if *alpha != 0 {
+                            *dest = foreground
+                                .blend_over(Color::from_pixel(*dest), *alpha, self.gamma_correct)
+                                .to_pixel();
                         }
                     }
                 }
@@ -148,6 +282,106 @@ impl FrameBuffer {
         }
     }
 
+    pub fn fill<B, C>(
+        &mut self,
+        bounding_box: B,
+        top_left: C,
+        color: Color,
+        font: &FontManager,
+        buffer: &mut [u32],
+    ) where
+        B: Into<BoundingBox>,
+        C: Into<Coordinates>,
+    {
+        let bounding_box = bounding_box.into();
+        let top_left = top_left.into() + bounding_box.top_left;
+        let char_width = font.character_width();
+        let char_height = font.character_height();
+
+        let pixel_box = self.clip((
+            top_left * Coordinates::from((char_width as isize, char_height as isize)),
+            bounding_box.width * char_width,
+            bounding_box.height * char_height,
+        ));
+
+        if let Some(pixel_box) = pixel_box {
+            let pixel = color.to_pixel();
+
+            for dest_line in self.lines(pixel_box, buffer) {
+                dest_line.fill(pixel);
+            }
+        }
+    }
+
+    /// Draws a one pixel wide line of `color` along the left edge of every `spacing`th column and
+    /// the top edge of every `spacing`th row of the grid, `origin` being the absolute coordinates
+    /// of the top left cell of `bounding_box`.
+    pub fn guides<B, C>(
+        &mut self,
+        bounding_box: B,
+        origin: C,
+        spacing: usize,
+        color: Color,
+        font: &FontManager,
+        buffer: &mut [u32],
+    ) where
+        B: Into<BoundingBox>,
+        C: Into<Coordinates>,
+    {
+        let bounding_box = bounding_box.into();
+        let origin = origin.into();
+        let char_width = font.character_width() as isize;
+        let char_height = font.character_height() as isize;
+        let pixel = color.to_pixel();
+        let spacing = spacing as isize;
+
+        let top = bounding_box.top_left.y * char_height;
+        let left = bounding_box.top_left.x * char_width;
+        let width = bounding_box.width * char_width as usize;
+        let height = bounding_box.height * char_height as usize;
+
+        for column in 0..bounding_box.width as isize {
+            if (origin.x + column).rem_euclid(spacing) == 0 {
+                if let Some(b) = self.clip(((left + column * char_width, top), 1, height)) {
+                    for dest_line in self.lines(b, buffer) {
+                        dest_line.fill(pixel);
+                    }
+                }
+            }
+        }
+
+        for row in 0..bounding_box.height as isize {
+            if (origin.y + row).rem_euclid(spacing) == 0 {
+                if let Some(b) = self.clip(((left, top + row * char_height), width, 1)) {
+                    for dest_line in self.lines(b, buffer) {
+                        dest_line.fill(pixel);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Restricts a bounding box in pixels to the part that lies inside the frame buffer.
+    fn clip<B>(&self, bounding_box: B) -> Option<BoundingBox>
+    where
+        B: Into<BoundingBox>,
+    {
+        let bounding_box = bounding_box.into();
+
+        let left = bounding_box.top_left.x.max(0);
+        let top = bounding_box.top_left.y.max(0);
+        let right = (bounding_box.top_left.x + bounding_box.width as isize).min(self.width as _);
+        let bottom = (bounding_box.top_left.y + bounding_box.height as isize).min(self.height as _);
+
+        (right > left && bottom > top).then(|| {
+            BoundingBox::new(
+                (left, top),
+                (right - left) as usize,
+                (bottom - top) as usize,
+            )
+        })
+    }
+
     pub fn invert<B, C>(
         &mut self,
         bounding_box: B,
@@ -172,7 +406,11 @@ impl FrameBuffer {
             let character_top_left =
                 coord * Coordinates::from((char_width as isize, char_height as isize));
 
-            for dest_line in self.lines((character_top_left, char_width, char_height), buffer) {
+            let Some(pixel_box) = self.clip((character_top_left, char_width, char_height)) else {
+                continue;
+            };
+
+            for dest_line in self.lines(pixel_box, buffer) {
                 for v in dest_line {
                     let [x, a, b, c] = v.to_be_bytes();
 
@@ -183,6 +421,14 @@ impl FrameBuffer {
     }
 }
 
+/// A panel of text drawn over the drawing, like the character palette.
+pub struct Overlay {
+    /// The text of the panel, its size being that of the panel and its view what is shown.
+    pub area: TextArea,
+    /// The cell drawn inverted, in absolute coordinates of the area.
+    pub selected: Option<Coordinates>,
+}
+
 pub struct Canvas {
     frame_buffer: FrameBuffer,
     surface: Surface<Rc<Window>, Rc<Window>>,
@@ -190,32 +436,54 @@ pub struct Canvas {
     pub top_line: TextArea,
     pub draw_area: TextArea,
     pub bottom_line: TextArea,
+    pub theme: Theme,
+    pub guide_spacing: usize,
+    pub selection: Option<BoundingBox>,
+    /// The cells drawn inverted, like the matches of a search.
+    pub highlights: Vec<BoundingBox>,
+    /// The text being composed by the input method, drawn at the cursor, and the position of the
+    /// caret in it, in characters.
+    pub preedit: Option<(String, usize)>,
+    /// The panel drawn over everything else, if any.
+    pub overlay: Option<Overlay>,
 }
 
 impl Canvas {
     pub fn new(
+        window: Rc<Window>,
         font: Font,
         font_size: f32,
-        surface: Surface<Rc<Window>, Rc<Window>>,
-        width: usize,
-        height: usize,
+        gamma_correct: bool,
+        theme: Theme,
     ) -> Self {
-        let font = FontManager::new(font, font_size);
+        let context = Context::new(window.clone()).unwrap();
+        let surface = Surface::new(&context, window.clone()).unwrap();
+
+        let size = window.inner_size();
+        let (width, height) = (size.width as usize, size.height as usize);
+
+        let font = FontManager::new(font, font_size, window.scale_factor());
 
         let top_line = TextArea::new(width / font.character_width(), 1);
         let draw_area = TextArea::new(
             width / font.character_width(),
-            (height / font.character_height()) - 2,
+            (height / font.character_height()).saturating_sub(2),
         );
         let bottom_line = TextArea::new(width / font.character_width(), 1);
 
         Self {
-            frame_buffer: FrameBuffer::new(width, height),
+            frame_buffer: FrameBuffer::new(width, height, gamma_correct),
             surface,
             font,
             top_line,
             draw_area,
             bottom_line,
+            theme,
+            guide_spacing: 0,
+            selection: None,
+            highlights: Vec::new(),
+            preedit: None,
+            overlay: None,
         }
     }
 
@@ -229,42 +497,195 @@ impl Canvas {
 
     pub fn render(&mut self) {
         let char_height = self.font.character_height();
+        let bottom_row = ((self.frame_buffer.height / char_height).saturating_sub(1)) as isize;
+        let columns = self.frame_buffer.width / self.font.character_width();
         let mut buffer = self.surface.buffer_mut().unwrap();
 
-        for v in buffer.iter_mut() {
-            *v = 0
-        }
+        buffer.fill(self.theme.background.to_pixel());
 
         self.top_line.bounding_box.top_left = (0, 0).into();
         self.bottom_line.bounding_box.top_left = (0, 0).into();
 
-        self.frame_buffer
-            .draw(self.top_line.chars(), &mut self.font, (0, 0), &mut buffer);
+        for row in [0, bottom_row] {
+            self.frame_buffer.fill(
+                ((0, row), columns + 1, 1),
+                (0, 0),
+                self.theme.status_background,
+                &self.font,
+                &mut buffer,
+            );
+        }
+
+        if self.guide_spacing > 0 {
+            self.frame_buffer.guides(
+                (
+                    (0, 1),
+                    self.draw_area.bounding_box.width,
+                    self.draw_area.bounding_box.height,
+                ),
+                self.draw_area.bounding_box.top_left,
+                self.guide_spacing,
+                self.theme.grid,
+                &self.font,
+                &mut buffer,
+            );
+        }
+
+        if let Some(selection) = self.selection {
+            let view = self.draw_area.bounding_box.top_left;
+
+            self.frame_buffer.fill(
+                (selection.top_left - view, selection.width, selection.height),
+                (0, 1),
+                self.theme.selection,
+                &self.font,
+                &mut buffer,
+            );
+        }
+
+        self.frame_buffer.draw(
+            self.top_line.chars(),
+            &mut self.font,
+            (0, 0),
+            self.theme.status_foreground,
+            &mut buffer,
+        );
+
+        self.frame_buffer.draw(
+            self.draw_area.chars(),
+            &mut self.font,
+            (0, 1),
+            self.theme.foreground,
+            &mut buffer,
+        );
 
-        self.frame_buffer
-            .draw(self.draw_area.chars(), &mut self.font, (0, 1), &mut buffer);
+        for highlight in &self.highlights {
+            let view = self.draw_area.bounding_box.top_left;
+
+            self.frame_buffer.invert(
+                (highlight.top_left - view, highlight.width, highlight.height),
+                (0, 1),
+                &self.font,
+                &mut buffer,
+            );
+        }
 
         self.frame_buffer.draw(
             self.bottom_line.chars(),
             &mut self.font,
-            (
-                0,
-                ((self.frame_buffer.height / char_height).saturating_sub(1)) as _,
-            ),
+            (0, bottom_row),
+            self.theme.status_foreground,
             &mut buffer,
         );
 
-        // make cursor inverted
-        self.frame_buffer.invert(
-            ((self.draw_area.cursor_relative_position()), 1, 1),
+        let mut cursor = self.draw_area.cursor_relative_position();
+        let cursor_position = self.draw_area.cursor_absolute_position();
+        let mut cursor_width = self.draw_area.width_at(cursor_position);
+        let mut under_cursor = self.draw_area.grapheme_at(cursor_position);
+
+        if let Some((text, caret)) = &self.preedit {
+            let graphemes = text_area::lay_out(text).collect::<Vec<_>>();
+            let columns = graphemes
+                .last()
+                .map_or(0, |(x, g)| *x as usize + grapheme_width(g));
+
+            self.frame_buffer.fill(
+                (cursor, columns, 1),
+                (0, 1),
+                self.theme.selection,
+                &self.font,
+                &mut buffer,
+            );
+
+            self.frame_buffer.draw(
+                graphemes
+                    .iter()
+                    .flat_map(|(x, g)| g.chars().map(move |c| (cursor + (*x, 0).into(), c))),
+                &mut self.font,
+                (0, 1),
+                self.theme.foreground,
+                &mut buffer,
+            );
+
+            // The caret is counted in characters, and lands on the grapheme starting there
+            let mut start = 0;
+            let at_caret = graphemes.iter().find(|(_, g)| {
+                let found = start >= *caret;
+                start += g.chars().count();
+                found
+            });
+
+            (cursor.x, cursor_width, under_cursor) = match at_caret {
+                Some((x, g)) => (cursor.x + x, grapheme_width(g), Some(*g)),
+                None => (cursor.x + columns as isize, 1, None),
+            };
+        }
+
+        self.frame_buffer.fill(
+            (cursor, cursor_width, 1),
             (0, 1),
+            self.theme.cursor,
             &self.font,
             &mut buffer,
         );
 
+        if let Some(g) = under_cursor {
+            self.frame_buffer.draw(
+                g.chars().map(|c| (cursor, c)),
+                &mut self.font,
+                (0, 1),
+                self.theme.cursor_text,
+                &mut buffer,
+            );
+        }
+
+        if let Some(overlay) = &mut self.overlay {
+            // The panel covers the drawing but for a margin, its text being padded by a cell
+            let top_left = Coordinates::from((2, 3));
+            let view = overlay.area.bounding_box;
+
+            self.frame_buffer.fill(
+                ((-1, -1), view.width + 2, view.height + 2),
+                top_left,
+                self.theme.status_background,
+                &self.font,
+                &mut buffer,
+            );
+
+            self.frame_buffer.draw(
+                overlay.area.chars(),
+                &mut self.font,
+                top_left,
+                self.theme.status_foreground,
+                &mut buffer,
+            );
+
+            if let Some(selected) = overlay.selected {
+                self.frame_buffer.invert(
+                    (selected - view.top_left, overlay.area.width_at(selected), 1),
+                    top_left,
+                    &self.font,
+                    &mut buffer,
+                );
+            }
+        }
+
         buffer.present().unwrap();
     }
 
+    /// The area covered by the cursor, in physical pixels, used to place the input method popup.
+    pub fn cursor_area(&self) -> BoundingBox {
+        let char_width = self.font.character_width();
+        let char_height = self.font.character_height();
+        let cursor = self.draw_area.cursor_relative_position() + (0, 1).into();
+
+        BoundingBox::new(
+            cursor * Coordinates::from((char_width as isize, char_height as isize)),
+            char_width,
+            char_height,
+        )
+    }
+
     pub fn font_size(&self) -> f32 {
         self.font.font_size
     }
@@ -278,33 +699,32 @@ impl Canvas {
         {
             self.font.font_size = old_value
         } else {
-            self.top_line
-                .set_size(self.width() / self.font.character_width(), 1);
-            self.draw_area.set_size(
-                self.width() / self.font.character_width(),
-                (self.height() / self.font.character_height()).saturating_sub(1),
-            );
-            self.bottom_line
-                .set_size(self.width() / self.font.character_width(), 1);
+            self.layout();
         }
     }
 
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.font.scale_factor = scale_factor;
+        self.layout();
+    }
+
     pub fn resize(&mut self, width: NonZeroU32, height: NonZeroU32) {
         self.surface.resize(width, height).unwrap();
 
-        let width = width.get() as usize;
-        let height = height.get() as usize;
+        self.frame_buffer.width = width.get() as usize;
+        self.frame_buffer.height = height.get() as usize;
+
+        self.layout();
+    }
 
-        self.frame_buffer.width = width;
-        self.frame_buffer.height = height;
+    /// Recomputes the size in cells of every text area from the size of the frame buffer and the
+    /// current character size.
+    fn layout(&mut self) {
+        let columns = self.width() / self.font.character_width().max(1);
+        let rows = self.height() / self.font.character_height().max(1);
 
-        self.top_line
-            .set_size(width / self.font.character_width(), 1);
-        self.draw_area.set_size(
-            width / self.font.character_width(),
-            (height / self.font.character_height()).saturating_sub(1),
-        );
-        self.bottom_line
-            .set_size(width / self.font.character_width(), 1);
+        self.top_line.set_size(columns, 1);
+        self.draw_area.set_size(columns, rows.saturating_sub(2));
+        self.bottom_line.set_size(columns, 1);
     }
 }
@@ -0,0 +1,798 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    draw::RectangleFill,
+    export::ExportFormat,
+    glyphs::{Arrowhead, LineStyle},
+    grid::GridEdit,
+    keymap::{Action, BoxMode, ColorMode, ExtraMode, InputMode, TextMode},
+    objects::Align,
+    table::TableEdit,
+    text_area::Direction,
+    transform::Transform,
+};
+
+/// Every command understood by the command line, used for completion.
+const COMMANDS: &[&str] = &[
+    "backspace",
+    "bind",
+    "box",
+    "check",
+    "connect",
+    "convert",
+    "delete",
+    "dot",
+    "dot!",
+    "draw",
+    "e",
+    "e!",
+    "edit",
+    "edit!",
+    "export",
+    "fill",
+    "find",
+    "findnext",
+    "findprevious",
+    "flip",
+    "goto",
+    "insert",
+    "jump",
+    "label",
+    "mark",
+    "mode",
+    "move",
+    "movebox",
+    "newline",
+    "palette",
+    "play",
+    "put",
+    "q",
+    "q!",
+    "quit",
+    "quit!",
+    "rectangle",
+    "redo",
+    "region",
+    "repeat",
+    "replace",
+    "resizebox",
+    "rotate",
+    "savemacros",
+    "sequence",
+    "set",
+    "source",
+    "style",
+    "tab",
+    "table",
+    "textbox",
+    "theme",
+    "tree",
+    "type",
+    "unbox",
+    "undo",
+    "w",
+    "wq",
+    "write",
+    "zoom",
+];
+
+/// Every mode that can be entered with `:mode`.
+const MODES: &[&str] = &["box", "text", "color", "extra"];
+
+/// Every option that can be changed with `:set`.
+const OPTIONS: &[&str] = &[
+    "arrowhead",
+    "fontsize",
+    "guides",
+    "searchcolumns",
+    "tabstops",
+    "textalign",
+    "textpadding",
+    "textwidth",
+    "theme",
+    "treebranch",
+];
+
+fn expect_argument<'a>(
+    arguments: &mut impl Iterator<Item = &'a str>,
+    what: &str,
+) -> Result<&'a str, String> {
+    arguments.next().ok_or_else(|| format!("missing {what}"))
+}
+
+/// The file name starting with the argument `first` of `command`, which is the rest of the line so
+/// that it can hold spaces. The remaining arguments are used up.
+fn path_from<'a>(
+    command: &'a str,
+    first: &'a str,
+    arguments: &mut impl Iterator<Item = &'a str>,
+) -> PathBuf {
+    // Arguments are slices of the command, so this is where `first` starts in it
+    let start = first.as_ptr() as usize - command.as_ptr() as usize;
+    arguments.for_each(drop);
+
+    unescape(command[start..].trim_end()).into()
+}
+
+fn expect_path<'a>(
+    command: &'a str,
+    arguments: &mut impl Iterator<Item = &'a str>,
+) -> Result<PathBuf, String> {
+    let first = expect_argument(arguments, "file name")?;
+    Ok(path_from(command, first, arguments))
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Reads a character written either as itself or as its code point, like `U+2500`.
+fn parse_char(s: &str) -> Option<char> {
+    match s.strip_prefix("U+").or_else(|| s.strip_prefix("u+")) {
+        Some(code) => u32::from_str_radix(code, 16).ok().and_then(char::from_u32),
+        None => single_char(s),
+    }
+}
+
+/// Reads free text, like a search or a file name, written by [`escape`]. Other backslashes are
+/// left as they are, so that they don't need doubling when typed.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('\\' | '|'))) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Splits a line holding several commands, like a macro definition, at every `|` that isn't
+/// escaped inside free text.
+pub fn split(line: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '|' => {
+                commands.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    commands.push(&line[start..]);
+    commands
+}
+
+/// Turns a command line, without its leading `:`, into the actions it stands for.
+pub fn parse(command: &str) -> Result<Vec<Action>, String> {
+    let mut words = command.split_whitespace();
+
+    let Some(name) = words.next() else {
+        return Ok(vec![]);
+    };
+
+    let actions = match name {
+        "w" | "write" | "wq" => {
+            let path = words
+                .next()
+                .map(|first| path_from(command, first, &mut words));
+
+            vec![match name {
+                "wq" => Action::SaveAndQuit(path),
+                _ => Action::Save(path),
+            }]
+        }
+        "e" | "edit" | "e!" | "edit!" => {
+            vec![Action::Open(
+                expect_path(command, &mut words)?,
+                name.ends_with('!'),
+            )]
+        }
+        "dot" | "dot!" => vec![Action::ImportDot(
+            expect_path(command, &mut words)?,
+            name.ends_with('!'),
+        )],
+        "q" | "quit" => vec![Action::Quit(false)],
+        "q!" | "quit!" => vec![Action::Quit(true)],
+        "source" => vec![Action::Source(expect_path(command, &mut words)?)],
+        "mark" | "jump" | "play" => {
+            let register = expect_argument(&mut words, "register name")?;
+            let register =
+                single_char(register).ok_or_else(|| format!("invalid register `{register}`"))?;
+
+            vec![match name {
+                "mark" => Action::SetMark(register),
+                "jump" => Action::JumpToMark(register),
+                _ => Action::PlayMacro(register),
+            }]
+        }
+        "move" => {
+            let direction = expect_argument(&mut words, "direction")?;
+            let direction = direction
+                .parse::<Direction>()
+                .map_err(|_| format!("unknown direction `{direction}`"))?;
+            let amount = match words.next() {
+                Some(amount) => amount
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid amount `{amount}`"))?,
+                None => 1,
+            };
+
+            vec![Action::MoveCursor(direction, amount)]
+        }
+        "bind" => {
+            let register = expect_argument(&mut words, "register name")?;
+            let register = single_char(register)
+                .filter(|r| r.is_alphanumeric())
+                .ok_or_else(|| format!("invalid register `{register}`"))?;
+            let c = expect_argument(&mut words, "character")?;
+            let c = parse_char(c).ok_or_else(|| format!("invalid character `{c}`"))?;
+
+            vec![Action::BindChar(register, c)]
+        }
+        "draw" | "put" | "type" => {
+            let c = expect_argument(&mut words, "character")?;
+            let c = parse_char(c).ok_or_else(|| format!("invalid character `{c}`"))?;
+
+            vec![match name {
+                "draw" => Action::DrawCharAtCursor(c),
+                "put" => Action::PutChar(c),
+                _ => Action::TypeChar(c),
+            }]
+        }
+        "newline" => vec![Action::NewLine],
+        "tab" => vec![Action::Tab],
+        "backspace" => vec![Action::Backspace],
+        "insert" | "delete" => vec![match (name, words.next()) {
+            ("insert", None) => Action::ToggleInsert,
+            (_, None) => Action::DeleteAtCursor,
+            ("insert", Some("row")) => Action::EditGrid(GridEdit::InsertRow),
+            ("insert", Some("column")) => Action::EditGrid(GridEdit::InsertColumn),
+            (_, Some("row")) => Action::EditGrid(GridEdit::DeleteRow),
+            (_, Some("column")) => Action::EditGrid(GridEdit::DeleteColumn),
+            (_, Some(other)) => return Err(format!("expected `row` or `column`, found `{other}`")),
+        }],
+        "rectangle" => vec![Action::Rectangle(match words.next() {
+            None => RectangleFill::Outline,
+            Some("clear") => RectangleFill::Clear,
+            Some("fill") => {
+                let c = expect_argument(&mut words, "fill character")?;
+                RectangleFill::Fill(
+                    parse_char(c).ok_or_else(|| format!("invalid character `{c}`"))?,
+                )
+            }
+            Some(other) => return Err(format!("expected `clear` or `fill`, found `{other}`")),
+        })],
+        "find" | "replace" | "palette" => {
+            // The text is the rest of the line, spaces included
+            let text = unescape(command.trim_start()[name.len()..].trim());
+            words.by_ref().for_each(drop);
+
+            vec![match name {
+                "find" => Action::Find(text),
+                "palette" => Action::OpenPalette(text),
+                _ => Action::ReplaceAll(text),
+            }]
+        }
+        "check" => vec![match words.next() {
+            None | Some("next") => Action::NextIssue,
+            Some("previous") => Action::PreviousIssue,
+            Some("fix") => Action::FixIssues,
+            Some(other) => {
+                return Err(format!(
+                    "expected `next`, `previous` or `fix`, found `{other}`"
+                ))
+            }
+        }],
+        "findnext" => vec![Action::FindNext],
+        "findprevious" => vec![Action::FindPrevious],
+        "fill" => vec![
+            match expect_argument(&mut words, "fill character or `clear`")? {
+                "clear" => Action::ClearRegion,
+                c => Action::Fill(parse_char(c).ok_or_else(|| format!("invalid character `{c}`"))?),
+            },
+        ],
+        "connect" => vec![Action::Connector],
+        "box" => vec![Action::AddShape],
+        "textbox" => vec![Action::AddTextBox],
+        "unbox" => vec![Action::DeleteShape],
+        "table" => match expect_argument(&mut words, "row count or `import`")? {
+            "import" => vec![Action::ImportTable(expect_path(command, &mut words)?)],
+            edit @ ("insert" | "delete") => {
+                vec![Action::EditTable(
+                    match (edit, expect_argument(&mut words, "`row` or `column`")?) {
+                        ("insert", "row") => TableEdit::InsertRow,
+                        ("insert", "column") => TableEdit::InsertColumn,
+                        (_, "row") => TableEdit::DeleteRow,
+                        (_, "column") => TableEdit::DeleteColumn,
+                        (_, other) => {
+                            return Err(format!("expected `row` or `column`, found `{other}`"))
+                        }
+                    },
+                )]
+            }
+            rows => {
+                let count = |v: &str| match v.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(format!("invalid count `{v}`")),
+                };
+                let columns = expect_argument(&mut words, "column count")?;
+
+                vec![Action::AddTable(count(rows)?, count(columns)?)]
+            }
+        },
+        "sequence" => match expect_argument(&mut words, "file name or `source`")? {
+            "source" => vec![Action::ExtractSequence(expect_path(command, &mut words)?)],
+            first => vec![Action::InsertSequence(path_from(
+                command, first, &mut words,
+            ))],
+        },
+        "tree" => match expect_argument(&mut words, "file name or `extract`")? {
+            "extract" => vec![Action::ExtractTree(expect_path(command, &mut words)?)],
+            first => vec![Action::InsertTree(path_from(command, first, &mut words))],
+        },
+        "label" => {
+            // The label is the rest of the line, spaces included
+            let label = command.trim_start()[name.len()..].trim();
+            words.by_ref().for_each(drop);
+
+            vec![Action::SetLabel(unescape(label))]
+        }
+        "movebox" | "resizebox" => {
+            let direction = expect_argument(&mut words, "direction")?;
+            let direction = direction
+                .parse::<Direction>()
+                .map_err(|_| format!("unknown direction `{direction}`"))?;
+
+            vec![if name == "movebox" {
+                Action::MoveShape(direction)
+            } else {
+                match expect_argument(&mut words, "`grow` or `shrink`")? {
+                    "grow" => Action::ResizeShape(direction, true),
+                    "shrink" => Action::ResizeShape(direction, false),
+                    other => return Err(format!("expected `grow` or `shrink`, found `{other}`")),
+                }
+            }]
+        }
+        "undo" => vec![Action::Undo],
+        "redo" => vec![Action::Redo],
+        "repeat" => vec![Action::Repeat],
+        "savemacros" => vec![Action::SaveMacros],
+        "zoom" => match expect_argument(&mut words, "`in` or `out`")? {
+            "in" => vec![Action::IncreaseFontSize],
+            "out" => vec![Action::ReduceFontSize],
+            other => return Err(format!("expected `in` or `out`, found `{other}`")),
+        },
+        "region" => match expect_argument(&mut words, "`next` or `previous`")? {
+            "next" => vec![Action::NextRegion],
+            "previous" => vec![Action::PreviousRegion],
+            other => return Err(format!("expected `next` or `previous`, found `{other}`")),
+        },
+        "theme" => match expect_argument(&mut words, "`next`")? {
+            "next" => vec![Action::NextTheme],
+            other => return Err(format!("expected `next`, found `{other}`")),
+        },
+        "mode" => vec![Action::Transition(
+            match expect_argument(&mut words, "mode name")? {
+                "box" => InputMode::Box(BoxMode),
+                "text" => InputMode::Text(TextMode),
+                "color" => InputMode::Color(ColorMode),
+                "extra" => InputMode::Extra(ExtraMode { buffer: Vec::new() }),
+                other => return Err(format!("unknown mode `{other}`")),
+            },
+        )],
+        "goto" => {
+            let arguments = words.by_ref().collect::<Vec<_>>().join(" ");
+            let (x, y) = arguments
+                .split_once([',', ' '])
+                .ok_or_else(|| "expected coordinates as `x,y`".to_string())?;
+            let coordinate = |v: &str| {
+                v.trim()
+                    .parse::<isize>()
+                    .map_err(|_| format!("invalid coordinate `{}`", v.trim()))
+            };
+
+            vec![Action::GoTo((coordinate(x)?, coordinate(y)?).into())]
+        }
+        "style" => {
+            let style = expect_argument(&mut words, "line style")?;
+
+            vec![Action::SetLineStyle(
+                style
+                    .parse::<LineStyle>()
+                    .map_err(|_| format!("unknown line style `{style}`"))?,
+            )]
+        }
+        "flip" | "rotate" => {
+            let transform = match (name, expect_argument(&mut words, "direction")?) {
+                ("flip", "horizontal") => Transform::FlipHorizontal,
+                ("flip", "vertical") => Transform::FlipVertical,
+                ("rotate", "clockwise") => Transform::RotateClockwise,
+                ("rotate", "counterclockwise") => Transform::RotateCounterclockwise,
+                (_, other) => return Err(format!("unknown direction `{other}`")),
+            };
+            let keep_text = match words.next() {
+                Some("keeptext") => true,
+                Some(other) => return Err(format!("unexpected argument `{other}`")),
+                None => false,
+            };
+
+            vec![Action::Transform(transform, keep_text)]
+        }
+        "convert" => {
+            let style = expect_argument(&mut words, "line style")?;
+
+            vec![Action::Convert(
+                style
+                    .parse::<LineStyle>()
+                    .map_err(|_| format!("unknown line style `{style}`"))?,
+            )]
+        }
+        "export" => {
+            let format = expect_argument(&mut words, "export format")?;
+            let format = format
+                .parse::<ExportFormat>()
+                .map_err(|_| format!("unknown export format `{format}`"))?;
+
+            vec![Action::Export(format, expect_path(command, &mut words)?)]
+        }
+        "set" => {
+            let option = expect_argument(&mut words, "option name")?;
+            let value = expect_argument(&mut words, "option value")?;
+            let invalid = || format!("invalid value `{value}` for `{option}`");
+
+            match option {
+                "arrowhead" => vec![Action::SetArrowhead(value.parse().map_err(|_| invalid())?)],
+                "fontsize" => vec![Action::SetFontSize(value.parse().map_err(|_| invalid())?)],
+                "guides" => vec![Action::SetGuideSpacing(
+                    value.parse().map_err(|_| invalid())?,
+                )],
+                "searchcolumns" => vec![Action::SetSearchColumns(
+                    value.parse().map_err(|_| invalid())?,
+                )],
+                "tabstops" => vec![Action::SetTabStops(value.parse().map_err(|_| invalid())?)],
+                "textwidth" => vec![Action::SetTextWidth(value.parse().map_err(|_| invalid())?)],
+                "textalign" => vec![Action::SetTextAlign(value.parse().map_err(|_| invalid())?)],
+                "textpadding" => vec![Action::SetTextPadding(
+                    value.parse().map_err(|_| invalid())?,
+                )],
+                "treebranch" => vec![Action::SetTreeBranch(value.parse().map_err(|_| invalid())?)],
+                "theme" => vec![Action::SetTheme(value.to_string())],
+                _ => return Err(format!("unknown option `{option}`")),
+            }
+        }
+        _ => return Err(format!("unknown command `{name}`")),
+    };
+
+    match words.next() {
+        Some(extra) => Err(format!("unexpected argument `{extra}`")),
+        None => Ok(actions),
+    }
+}
+
+/// The file names starting with `prefix`, directories ending with a `/`.
+fn complete_path(prefix: &str) -> Vec<String> {
+    let (directory, file) = match prefix.rfind('/') {
+        Some(i) => (&prefix[..=i], &prefix[i + 1..]),
+        None => ("", prefix),
+    };
+
+    let Ok(entries) = std::fs::read_dir(if directory.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(directory)
+    }) else {
+        return Vec::new();
+    };
+
+    let mut candidates = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.file_type().ok()?.is_dir();
+
+            (name.starts_with(file) && (!name.starts_with('.') || file.starts_with('.')))
+                .then(|| format!("{directory}{name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort();
+    candidates
+}
+
+fn complete_word<I, S>(prefix: &str, words: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: ToString,
+{
+    words
+        .into_iter()
+        .map(|w| w.to_string())
+        .filter(|w| w.starts_with(prefix))
+        .collect()
+}
+
+/// The possible completions of the last word of `command`, each returned as the whole command
+/// line it would produce.
+pub fn complete(command: &str, themes: &[String]) -> Vec<String> {
+    let words = command.split(' ').collect::<Vec<_>>();
+    let (last, previous) = words.split_last().unwrap();
+    let head = &command[..command.len() - last.len()];
+
+    let candidates = match previous {
+        [] => complete_word(last, COMMANDS),
+        ["w" | "write" | "wq" | "e" | "edit" | "e!" | "edit!" | "source" | "dot" | "dot!"]
+        | ["export", _] => complete_path(last),
+        ["style" | "convert"] => complete_word(last, LineStyle::ALL),
+        ["move" | "movebox" | "resizebox"] => complete_word(last, Direction::ALL),
+        ["resizebox", _] => complete_word(last, ["grow", "shrink"]),
+        ["table"] => complete_word(last, ["import", "insert", "delete"]),
+        ["table", "import"] | ["tree", "extract"] | ["sequence", "source"] => complete_path(last),
+        ["tree" | "sequence"] => {
+            let keyword = if previous[0] == "tree" {
+                "extract"
+            } else {
+                "source"
+            };
+            let mut candidates = complete_word(last, [keyword]);
+            candidates.extend(complete_path(last));
+            candidates
+        }
+        ["table", "insert" | "delete"] | ["insert" | "delete"] => {
+            complete_word(last, ["row", "column"])
+        }
+        ["flip"] => complete_word(last, ["horizontal", "vertical"]),
+        ["rotate"] => complete_word(last, ["clockwise", "counterclockwise"]),
+        ["flip" | "rotate", _] => complete_word(last, ["keeptext"]),
+        ["fill"] => complete_word(last, ["clear"]),
+        ["check"] => complete_word(last, ["next", "previous", "fix"]),
+        ["mode"] => complete_word(last, MODES),
+        ["zoom"] => complete_word(last, ["in", "out"]),
+        ["region"] => complete_word(last, ["next", "previous"]),
+        ["theme"] => complete_word(last, ["next"]),
+        ["rectangle"] => complete_word(last, ["clear", "fill"]),
+        ["export"] => complete_word(last, ExportFormat::ALL),
+        ["set"] => complete_word(last, OPTIONS),
+        ["set", "theme"] => complete_word(last, themes),
+        ["set", "arrowhead"] => complete_word(last, Arrowhead::ALL),
+        ["set", "textalign"] => complete_word(last, Align::ALL),
+        ["set", "searchcolumns"] => complete_word(last, ["true", "false"]),
+        _ => Vec::new(),
+    };
+
+    candidates
+        .into_iter()
+        .map(|c| format!("{head}{c}"))
+        .collect()
+}
+
+/// Writes a character so that [`parse_char`] reads it back, even inside a macro definition.
+fn escape_char(c: char) -> String {
+    if c.is_whitespace() || c.is_control() || c == '|' {
+        format!("U+{:04X}", c as u32)
+    } else {
+        c.to_string()
+    }
+}
+
+/// Writes free text so that [`unescape`] reads it back, even inside a macro definition.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn escape_path(path: &Path) -> String {
+    escape(&path.display().to_string())
+}
+
+/// The command line standing for `action`, if it has one, so that it can be parsed back with
+/// [`parse`].
+pub fn to_command(action: &Action) -> Option<String> {
+    Some(match action {
+        Action::CursorLeft => "move left".to_string(),
+        Action::CursorRight => "move right".to_string(),
+        Action::CursorUp => "move up".to_string(),
+        Action::CursorDown => "move down".to_string(),
+        Action::MoveCursor(direction, amount) => format!("move {direction} {amount}"),
+        Action::DrawCharAtCursor(c) => format!("draw {}", escape_char(*c)),
+        Action::PutChar(c) => format!("put {}", escape_char(*c)),
+        Action::DeleteAtCursor => "delete".to_string(),
+        Action::EditGrid(edit) => match edit {
+            GridEdit::InsertRow => "insert row",
+            GridEdit::InsertColumn => "insert column",
+            GridEdit::DeleteRow => "delete row",
+            GridEdit::DeleteColumn => "delete column",
+        }
+        .to_string(),
+        Action::TypeChar(c) => format!("type {}", escape_char(*c)),
+        Action::NewLine => "newline".to_string(),
+        Action::Tab => "tab".to_string(),
+        Action::Backspace => "backspace".to_string(),
+        Action::ToggleInsert => "insert".to_string(),
+        Action::Rectangle(RectangleFill::Outline) => "rectangle".to_string(),
+        Action::Rectangle(RectangleFill::Clear) => "rectangle clear".to_string(),
+        Action::Rectangle(RectangleFill::Fill(c)) => format!("rectangle fill {}", escape_char(*c)),
+        Action::SetTabStops(stops) => format!("set tabstops {stops}"),
+        Action::ReduceFontSize => "zoom out".to_string(),
+        Action::IncreaseFontSize => "zoom in".to_string(),
+        Action::Undo => "undo".to_string(),
+        Action::Redo => "redo".to_string(),
+        Action::Transition(mode) => match mode {
+            InputMode::Box(_) => "mode box".to_string(),
+            InputMode::Text(_) => "mode text".to_string(),
+            InputMode::Color(_) => "mode color".to_string(),
+            InputMode::Extra(_) => "mode extra".to_string(),
+            InputMode::Command(_) | InputMode::Palette(_) => return None,
+        },
+        Action::NextTheme => "theme next".to_string(),
+        Action::GoTo(position) => format!("goto {},{}", position.x, position.y),
+        Action::SetMark(name) => format!("mark {name}"),
+        Action::JumpToMark(name) => format!("jump {name}"),
+        Action::NextRegion => "region next".to_string(),
+        Action::PreviousRegion => "region previous".to_string(),
+        Action::Repeat => "repeat".to_string(),
+        Action::PlayMacro(name) => format!("play {name}"),
+        Action::BindChar(register, c) => format!("bind {register} {}", escape_char(*c)),
+        Action::SaveMacros => "savemacros".to_string(),
+        Action::Find(pattern) if pattern.is_empty() => "find".to_string(),
+        Action::Find(pattern) => format!("find {}", escape(pattern)),
+        Action::NextIssue => "check".to_string(),
+        Action::PreviousIssue => "check previous".to_string(),
+        Action::FixIssues => "check fix".to_string(),
+        Action::FindNext => "findnext".to_string(),
+        Action::FindPrevious => "findprevious".to_string(),
+        Action::ReplaceAll(replacement) => format!("replace {}", escape(replacement)),
+        Action::SetSearchColumns(columns) => format!("set searchcolumns {columns}"),
+        Action::Fill(c) => format!("fill {}", escape_char(*c)),
+        Action::ClearRegion => "fill clear".to_string(),
+        Action::Connector => "connect".to_string(),
+        Action::AddShape => "box".to_string(),
+        Action::DeleteShape => "unbox".to_string(),
+        Action::AddTextBox => "textbox".to_string(),
+        Action::SetTextWidth(width) => format!("set textwidth {width}"),
+        Action::SetTextAlign(align) => format!("set textalign {align}"),
+        Action::SetTextPadding(padding) => format!("set textpadding {padding}"),
+        Action::AddTable(rows, columns) => format!("table {rows} {columns}"),
+        Action::ImportTable(path) => format!("table import {}", escape_path(path)),
+        Action::EditTable(edit) => match edit {
+            TableEdit::InsertRow => "table insert row",
+            TableEdit::InsertColumn => "table insert column",
+            TableEdit::DeleteRow => "table delete row",
+            TableEdit::DeleteColumn => "table delete column",
+        }
+        .to_string(),
+        Action::InsertTree(path) => format!("tree {}", escape_path(path)),
+        Action::ExtractTree(path) => format!("tree extract {}", escape_path(path)),
+        Action::InsertSequence(path) => format!("sequence {}", escape_path(path)),
+        Action::ExtractSequence(path) => format!("sequence source {}", escape_path(path)),
+        Action::SetTreeBranch(length) => format!("set treebranch {length}"),
+        Action::SetLabel(label) => format!("label {}", escape(label)),
+        Action::MoveShape(direction) => format!("movebox {direction}"),
+        Action::ResizeShape(direction, grow) => format!(
+            "resizebox {direction} {}",
+            if *grow { "grow" } else { "shrink" }
+        ),
+        Action::SetArrowhead(arrowhead) => format!("set arrowhead {arrowhead}"),
+        Action::SetLineStyle(style) => format!("style {style}"),
+        Action::Convert(style) => format!("convert {style}"),
+        Action::Transform(transform, keep_text) => format!(
+            "{}{}",
+            match transform {
+                Transform::FlipHorizontal => "flip horizontal",
+                Transform::FlipVertical => "flip vertical",
+                Transform::RotateClockwise => "rotate clockwise",
+                Transform::RotateCounterclockwise => "rotate counterclockwise",
+            },
+            if *keep_text { " keeptext" } else { "" }
+        ),
+        Action::SetFontSize(size) => format!("set fontsize {size}"),
+        Action::SetGuideSpacing(spacing) => format!("set guides {spacing}"),
+        Action::SetTheme(name) => format!("set theme {name}"),
+        Action::Save(Some(path)) => format!("w {}", escape_path(path)),
+        Action::Save(None) => "w".to_string(),
+        Action::SaveAndQuit(Some(path)) => format!("wq {}", escape_path(path)),
+        Action::SaveAndQuit(None) => "wq".to_string(),
+        Action::Open(path, force) => {
+            format!("e{} {}", if *force { "!" } else { "" }, escape_path(path))
+        }
+        Action::ImportDot(path, force) => {
+            format!("dot{} {}", if *force { "!" } else { "" }, escape_path(path))
+        }
+        Action::Export(format, path) => format!("export {format} {}", escape_path(path)),
+        Action::Source(path) => format!("source {}", escape_path(path)),
+        Action::Quit(false) => "q".to_string(),
+        Action::Quit(true) => "q!".to_string(),
+        Action::OpenCommandLine(_)
+        | Action::OpenPalette(_)
+        | Action::AwaitName(_)
+        | Action::ToggleRecording
+        | Action::RecordMacro(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_with_spaces_and_bars() {
+        for (command, path) in [
+            ("w my drawing.txt", "my drawing.txt"),
+            ("e!  ~/My Diagrams/x  ", "~/My Diagrams/x"),
+            ("export svg a b|c", "a b|c"),
+            ("tree extract  some tree", "some tree"),
+            ("sequence a\\|b c", "a|b c"),
+        ] {
+            let actions = parse(command).unwrap();
+            let line = to_command(&actions[0]).unwrap();
+
+            // Written back, the path survives being split out of a macro
+            let parsed = parse(split(&format!("{line} | undo"))[0]).unwrap();
+            assert_eq!(to_command(&parsed[0]).unwrap(), line, "{command}");
+            assert!(line.ends_with(&escape(path)), "{command}");
+        }
+    }
+
+    #[test]
+    fn theme_named_next() {
+        for (command, written) in [
+            ("theme next", "theme next"),
+            ("set theme next", "set theme next"),
+            ("set theme light", "set theme light"),
+        ] {
+            let actions = parse(command).unwrap();
+            assert_eq!(to_command(&actions[0]).unwrap(), written);
+        }
+
+        assert!(matches!(
+            parse("theme next").unwrap()[..],
+            [Action::NextTheme]
+        ));
+        assert!(matches!(
+            parse("set theme next").unwrap()[..],
+            [Action::SetTheme(_)]
+        ));
+    }
+
+    #[test]
+    fn completion_lists_every_command() {
+        for name in COMMANDS {
+            if let Err(e) = parse(name) {
+                assert!(!e.starts_with("unknown command"), "{e}");
+            }
+        }
+
+        // The names matched by the arms of `parse`, read from its source
+        let source = include_str!("command.rs");
+        let body = &source[source.find("pub fn parse(").unwrap()..];
+        let body = &body[..body.find("unknown command").unwrap()];
+        let names = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("        \""))
+            .flat_map(|arm| arm[..arm.find(" =>").unwrap()].split('|'))
+            .map(|name| name.trim().trim_matches('"'))
+            .collect::<Vec<_>>();
+
+        assert!(names.contains(&"rectangle"));
+        for name in names {
+            assert!(COMMANDS.contains(&name), "`{name}` is missing");
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    canvas::Color,
+    command::{self, to_command},
+    glyphs::Arrowhead,
+    keymap::Action,
+    objects::TextLayout,
+    theme::Theme,
+};
+
+/// Separates the commands of a macro in the `[macros]` section.
+const MACRO_SEPARATOR: &str = " | ";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Syntax { line: usize, message: String },
+    Value { key: String, value: String },
+    UnknownKey(String),
+    UnknownSection(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {e}", path.display()),
+            ConfigError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            ConfigError::Value { key, value } => write!(f, "invalid value for `{key}`: {value}"),
+            ConfigError::UnknownKey(key) => write!(f, "unknown key `{key}`"),
+            ConfigError::UnknownSection(name) => write!(f, "unknown section `[{name}]`"),
+        }
+    }
+}
+
+/// The raw `key = value` content of a configuration file, split by `[section]` headers.
+///
+/// Keys that appear before the first header belong to the section with an empty name.
+#[derive(Default)]
+struct Ini {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Ini {
+    fn parse(s: &str) -> Result<Self, ConfigError> {
+        let mut sections = vec![(String::new(), Vec::new())];
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']').ok_or_else(|| ConfigError::Syntax {
+                    line: i + 1,
+                    message: "unterminated section header".to_string(),
+                })?;
+
+                sections.push((name.trim().to_string(), Vec::new()));
+            } else if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+
+                sections
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push((key.trim().to_string(), value.to_string()));
+            } else {
+                return Err(ConfigError::Syntax {
+                    line: i + 1,
+                    message: format!("expected `key = value`, found `{line}`"),
+                });
+            }
+        }
+
+        Ok(Self { sections })
+    }
+
+    fn section<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.sections
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .flat_map(|(_, entries)| entries.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+    }
+
+    fn sections_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a [(String, String)])> {
+        self.sections
+            .iter()
+            .filter_map(move |(n, entries)| n.strip_prefix(prefix).map(|n| (n, entries.as_slice())))
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::Value {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Where Tab moves the cursor to in Text mode, in columns from where typing began.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabStops {
+    /// Explicit stops, in increasing order.
+    stops: Vec<usize>,
+    /// The distance between stops after the last explicit one.
+    interval: usize,
+}
+
+impl Default for TabStops {
+    fn default() -> Self {
+        Self {
+            stops: Vec::new(),
+            interval: 4,
+        }
+    }
+}
+
+impl TabStops {
+    pub fn next(&self, column: usize) -> usize {
+        match self.stops.iter().find(|s| **s > column) {
+            Some(stop) => *stop,
+            None => {
+                let last = self.stops.last().copied().unwrap_or(0);
+
+                last + ((column - last) / self.interval + 1) * self.interval
+            }
+        }
+    }
+}
+
+impl fmt::Display for TabStops {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stops.as_slice() {
+            [] => write!(f, "{}", self.interval),
+            stops => write!(
+                f,
+                "{}",
+                stops
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+impl FromStr for TabStops {
+    type Err = ();
+
+    /// Parses either a single interval, like `4`, or a list of increasing stops, like `8, 20, 32`,
+    /// the last gap being repeated after the end of the list.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stops = s
+            .split(',')
+            .map(|s| s.trim().parse::<usize>().map_err(|_| ()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if stops.contains(&0) || stops.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(());
+        }
+
+        match stops.as_slice() {
+            [] => Err(()),
+            [interval] => Ok(Self {
+                stops: Vec::new(),
+                interval: *interval,
+            }),
+            [.., a, b] => Ok(Self {
+                interval: b - a,
+                stops,
+            }),
+        }
+    }
+}
+
+pub struct Config {
+    /// The file the configuration was loaded from, where macros and favourites are saved.
+    pub path: Option<PathBuf>,
+    pub theme: Option<String>,
+    pub themes: Vec<Theme>,
+    pub guide_spacing: usize,
+    pub tab_stops: TabStops,
+    pub arrowhead: Arrowhead,
+    pub text_layout: TextLayout,
+    pub tree_branch: usize,
+    /// Whether searches look along columns as well as rows.
+    pub search_columns: bool,
+    /// The characters shown first in the palette, written as a string.
+    pub favourites: Vec<char>,
+    /// The recorded macros, written in the `[macros]` section as one register per key and the
+    /// commands of each macro separated by `|`.
+    pub macros: BTreeMap<char, Vec<Action>>,
+    /// What was left out of the configuration without failing to load it, like macros whose
+    /// commands no longer parse.
+    pub warnings: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: None,
+            theme: None,
+            themes: vec![Theme::dark(), Theme::light()],
+            guide_spacing: 0,
+            tab_stops: TabStops::default(),
+            arrowhead: Arrowhead::Triangle,
+            text_layout: TextLayout::default(),
+            tree_branch: 2,
+            search_columns: false,
+            favourites: Vec::new(),
+            macros: BTreeMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The configuration file used when none is given on the command line.
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .map(|p| p.join("boxdrawed").join("config.ini"))
+    }
+
+    /// Loads the configuration at `path`, falling back to the defaults if the file doesn't exist.
+    pub fn load(path: PathBuf) -> Result<Self, ConfigError> {
+        let config = match std::fs::read_to_string(&path) {
+            Ok(s) => Self::parse(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            ..config
+        })
+    }
+
+    pub fn parse(s: &str) -> Result<Self, ConfigError> {
+        let ini = Ini::parse(s)?;
+        let mut config = Self::default();
+
+        if let Some((name, _)) = ini
+            .sections
+            .iter()
+            .find(|(n, _)| !n.is_empty() && n != "macros" && !n.starts_with("theme."))
+        {
+            return Err(ConfigError::UnknownSection(name.clone()));
+        }
+
+        for (key, value) in ini.section("") {
+            match key {
+                "theme" => config.theme = Some(value.to_string()),
+                "guide_spacing" => config.guide_spacing = parse_value(key, value)?,
+                "tab_stops" => config.tab_stops = parse_value(key, value)?,
+                "arrowhead" => config.arrowhead = parse_value(key, value)?,
+                "text_width" => config.text_layout.width = parse_value(key, value)?,
+                "text_align" => config.text_layout.align = parse_value(key, value)?,
+                "text_padding" => config.text_layout.padding = parse_value(key, value)?,
+                "tree_branch" => config.tree_branch = parse_value(key, value)?,
+                "search_columns" => config.search_columns = parse_value(key, value)?,
+                "favourites" => {
+                    config.favourites = value.chars().filter(|c| !c.is_whitespace()).collect()
+                }
+                _ => return Err(ConfigError::UnknownKey(key.to_string())),
+            }
+        }
+
+        // A macro that doesn't load is left out, rather than the whole configuration
+        for (key, value) in ini.section("macros") {
+            let mut chars = key.chars();
+            let (Some(register), None) = (chars.next(), chars.next()) else {
+                config
+                    .warnings
+                    .push(format!("invalid macro register `{key}`"));
+                continue;
+            };
+
+            let actions = command::split(value)
+                .into_iter()
+                .map(|command| command::parse(command.trim()))
+                .collect::<Result<Vec<_>, _>>();
+
+            match actions {
+                Ok(actions) => {
+                    config
+                        .macros
+                        .insert(register, actions.into_iter().flatten().collect());
+                }
+                Err(e) => config
+                    .warnings
+                    .push(format!("macro @{register} left out: {e}")),
+            }
+        }
+
+        for (name, entries) in ini.sections_with_prefix("theme.") {
+            let mut theme = entries
+                .iter()
+                .find(|(k, _)| k == "base")
+                .and_then(|(_, base)| config.themes.iter().find(|t| &t.name == base))
+                .cloned()
+                .unwrap_or_else(Theme::dark);
+            theme.name = name.to_string();
+
+            for (key, value) in entries {
+                let color = || parse_value::<Color>(key, value);
+
+                match key.as_str() {
+                    "background" => theme.background = color()?,
+                    "foreground" => theme.foreground = color()?,
+                    "cursor" => theme.cursor = color()?,
+                    "cursor_text" => theme.cursor_text = color()?,
+                    "selection" => theme.selection = color()?,
+                    "status_background" => theme.status_background = color()?,
+                    "status_foreground" => theme.status_foreground = color()?,
+                    "grid" => theme.grid = color()?,
+                    // Already applied, as the other keys go on top of it
+                    "base" => {}
+                    _ => return Err(ConfigError::UnknownKey(format!("{name}.{key}"))),
+                }
+            }
+
+            if let Some(existing) = config.themes.iter_mut().find(|t| t.name == theme.name) {
+                *existing = theme;
+            } else {
+                config.themes.push(theme);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Replaces the `[macros]` section of the configuration file at `path`, leaving the rest of
+    /// the file untouched.
+    pub fn save_macros(
+        path: &Path,
+        macros: &BTreeMap<char, Vec<Action>>,
+    ) -> Result<(), ConfigError> {
+        let io_error = |e| ConfigError::Io(path.to_path_buf(), e);
+
+        let existing = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let mut in_macros = false;
+        let mut content = existing
+            .lines()
+            .filter(|line| {
+                let line = line.trim();
+
+                if line.starts_with('[') {
+                    in_macros = line == "[macros]";
+                }
+
+                !in_macros
+            })
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+
+        if !content.is_empty() && !content.ends_with("\n\n") {
+            content.push('\n');
+        }
+
+        content.push_str("[macros]\n");
+
+        for (register, actions) in macros {
+            let commands = actions
+                .iter()
+                .filter_map(to_command)
+                .collect::<Vec<_>>()
+                .join(MACRO_SEPARATOR);
+
+            content.push_str(&format!("{register} = {commands}\n"));
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+
+        std::fs::write(path, content).map_err(io_error)
+    }
+
+    /// Sets the `favourites` key of the configuration file at `path`, leaving the rest of the file
+    /// untouched.
+    pub fn save_favourites(path: &Path, favourites: &[char]) -> Result<(), ConfigError> {
+        let io_error = |e| ConfigError::Io(path.to_path_buf(), e);
+
+        let existing = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let line = format!("favourites = \"{}\"", favourites.iter().collect::<String>());
+        let mut lines = existing.lines().map(str::to_string).collect::<Vec<_>>();
+
+        // The key belongs before the first section header
+        let end = lines
+            .iter()
+            .position(|l| l.trim().starts_with('['))
+            .unwrap_or(lines.len());
+        let found = lines[..end].iter().position(|l| {
+            l.split_once('=')
+                .is_some_and(|(k, _)| k.trim() == "favourites")
+        });
+
+        match found {
+            Some(i) => lines[i] = line,
+            None => {
+                // After the last key, keeping the blank lines before the first header
+                let at = lines[..end]
+                    .iter()
+                    .rposition(|l| !l.trim().is_empty())
+                    .map_or(0, |i| i + 1);
+                lines.insert(at, line);
+
+                if at == end && end < lines.len() - 1 {
+                    lines.insert(at + 1, String::new());
+                }
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+
+        std::fs::write(path, lines.join("\n") + "\n").map_err(io_error)
+    }
+
+    /// Finds the index of the theme called `name`, if any.
+    pub fn theme_index(&self, name: &str) -> Option<usize> {
+        self.themes.iter().position(|t| t.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_macros_are_left_out() {
+        let config = Config::parse(
+            "[macros]\na = draw x | move right\nb = draw x | frobnicate\nlong = undo\n\
+             [theme.mine]\nbase = light\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.macros.keys().collect::<Vec<_>>(), [&'a']);
+        assert_eq!(config.macros[&'a'].len(), 2);
+        assert_eq!(config.warnings.len(), 2);
+        assert!(config.theme_index("mine").is_some());
+    }
+
+    #[test]
+    fn unknown_keys_and_sections() {
+        let cases = [
+            ("colour = red\n", "unknown key `colour`"),
+            (
+                "[theme.mine]\nborder = #000000\n",
+                "unknown key `mine.border`",
+            ),
+            ("[macro]\na = undo\n", "unknown section `[macro]`"),
+            ("[themes]\n", "unknown section `[themes]`"),
+            ("tab_stops = wide\n", "invalid value for `tab_stops`: wide"),
+        ];
+
+        for (input, expected) in cases {
+            let error = Config::parse(input).err().expect(input);
+            assert_eq!(error.to_string(), expected, "{input}");
+        }
+    }
+}
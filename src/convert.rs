@@ -0,0 +1,151 @@
+//! Conversion of drawings between plain ASCII art, like `+--+` boxes and `-->` arrows, and
+//! Unicode box drawing.
+//!
+//! ASCII characters are only taken for lines when their neighbours carry the line on, so that
+//! text like `well-known` or `C++` is left as it is.
+
+use crate::{
+    glyphs::{self, Arrowhead, Connections, LineStyle, DOWN, LEFT, RIGHT, UP},
+    text_area::{BoundingBox, Coordinates, Direction, TextArea},
+};
+
+/// The diagonal lines, which have no place in the box drawing glyph tables.
+const DIAGONALS: [(char, char); 2] = [('/', '╱'), ('\\', '╲')];
+
+/// The dashed lines drawn by the editor, along with their ASCII version.
+const DASHED: [(char, char); 2] = [('┄', '.'), ('┅', '.')];
+
+/// The directions the arrowheads of every kind point towards.
+pub fn arrowhead(c: char) -> Option<Direction> {
+    Arrowhead::ALL
+        .into_iter()
+        .filter(|a| *a != Arrowhead::None)
+        .find_map(|a| Direction::ALL.into_iter().find(|d| a.glyph(*d) == c))
+}
+
+/// The direction an ASCII arrowhead points towards.
+fn arrowhead_direction(c: char) -> Direction {
+    match c {
+        '^' => Direction::Up,
+        '>' => Direction::Right,
+        'v' => Direction::Down,
+        _ => Direction::Left,
+    }
+}
+
+/// Whether `c` has a line going towards `direction`, ASCII lines included.
+fn joins(c: Option<char>, direction: Direction) -> bool {
+    let Some(c) = c else {
+        return false;
+    };
+
+    let ascii = match direction {
+        Direction::Up | Direction::Down => "|+",
+        Direction::Left | Direction::Right => "-+",
+    };
+
+    ascii.contains(c)
+        || arrowhead(c).is_some_and(|d| d == direction.opposite())
+        || glyphs::connections(c).is_some_and(|(c, _)| c & glyphs::towards(direction) != 0)
+}
+
+/// What the ASCII character at `position` becomes in `style`, if it is part of a line.
+fn to_unicode(
+    area: &TextArea,
+    position: Coordinates,
+    style: LineStyle,
+    arrowhead: Arrowhead,
+) -> Option<char> {
+    let c = area.char_at(position)?;
+    let neighbour = |direction: Direction| area.char_at(position + direction.vector());
+    // Whether the neighbour in `direction` has a line coming back towards `position`
+    let joined = |direction: Direction| joins(neighbour(direction), direction.opposite());
+
+    let connections: Connections = match c {
+        '-' if joined(Direction::Left) || joined(Direction::Right) => LEFT | RIGHT,
+        '|' if joined(Direction::Up) || joined(Direction::Down) => UP | DOWN,
+        '+' => {
+            let connections = Direction::ALL
+                .into_iter()
+                .filter(|d| joined(*d))
+                .fold(0, |connections, d| connections | glyphs::towards(d));
+
+            // A lone `+` or one at the end of a line is more likely to be text
+            if connections.count_ones() < 2 {
+                return None;
+            }
+
+            connections
+        }
+        '<' | '>' | '^' | 'v' => {
+            let direction = arrowhead_direction(c);
+
+            // Arrowheads are at the end of the line they point along
+            return joined(direction.opposite()).then(|| arrowhead.glyph(direction));
+        }
+        '/' | '\\' => {
+            let (a, b) = match c {
+                '/' => ((1, -1), (-1, 1)),
+                _ => ((-1, -1), (1, 1)),
+            };
+            let continued = [a, b]
+                .into_iter()
+                .any(|d| area.char_at(position + d.into()) == Some(c));
+
+            return continued.then(|| DIAGONALS.iter().find(|(a, _)| *a == c).unwrap().1);
+        }
+        _ => return None,
+    };
+
+    Some(glyphs::glyph(connections, style))
+}
+
+/// What the character `c` becomes in pure ASCII, if it is part of a line.
+fn to_ascii(c: char) -> Option<char> {
+    if let Some((connections, _)) = glyphs::connections(c) {
+        return Some(glyphs::glyph(connections, LineStyle::Ascii));
+    }
+
+    arrowhead(c)
+        .map(|d| Arrowhead::Ascii.glyph(d))
+        .or_else(|| DIAGONALS.iter().find(|(_, u)| *u == c).map(|(a, _)| *a))
+        .or_else(|| DASHED.iter().find(|(u, _)| *u == c).map(|(_, a)| *a))
+        .filter(|a| *a != c)
+}
+
+/// Redraws the lines of `region`, or of the whole area, in `style`, as a single undo step.
+/// Converting to [`LineStyle::Ascii`] downgrades box drawing glyphs and arrowheads to ASCII,
+/// while other styles infer the connections of ASCII lines from their neighbours, arrowheads
+/// becoming `arrowhead` then. Box drawing glyphs are restyled either way.
+pub fn convert(
+    area: &mut TextArea,
+    region: Option<BoundingBox>,
+    style: LineStyle,
+    arrowhead: Arrowhead,
+) {
+    let changes = area
+        .graphemes()
+        .filter(|(position, _)| region.is_none_or(|r| r.contains(position)))
+        .filter_map(|(position, grapheme)| {
+            let mut chars = grapheme.chars();
+            let c = chars.next().filter(|_| chars.next().is_none())?;
+
+            let converted = match style {
+                LineStyle::Ascii => to_ascii(c),
+                _ if c.is_ascii() => to_unicode(area, position, style, arrowhead),
+                _ => Some(glyphs::restyle(c, style)).filter(|r| *r != c),
+            };
+
+            converted.map(|converted| (position, converted))
+        })
+        // Every change is found before any is made, so that they don't depend on each other
+        .collect::<Vec<_>>();
+
+    area.begin_undo_group();
+
+    for (position, c) in changes {
+        area.write_at(position, c);
+    }
+
+    area.end_undo_group();
+}
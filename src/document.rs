@@ -0,0 +1,643 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    glyphs::{Arrowhead, LineStyle},
+    objects::{Endpoint, Link, Objects, Shape, TextLayout},
+    sequence::{self, Sequence},
+    table::Table,
+    text_area::{self, grapheme_width, BoundingBox, Coordinates, Direction},
+};
+
+/// The first line of every document in the native format.
+const HEADER: &str = "boxdrawed 1";
+
+#[derive(Debug)]
+pub enum DocumentError {
+    Io(PathBuf, std::io::Error),
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            DocumentError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+/// The content of a drawing, as stored on disk.
+///
+/// The native format starts with a header line, followed by `[section]`s. The `[cells]` section
+/// holds one horizontal run of characters per line, as `x y run`, wide characters taking two
+/// columns of the run, and the `[marks]` section one named position per line, as `name x y`. The
+/// object layer goes in the `[shapes]` section, as `id x y width height style label`, and the
+/// `[links]` section, as `from to style arrowhead path label`, ends being either `x,y` or
+/// `#id:side`, the path being `x,y:steps` with one of `urdl` per step and the label being optional.
+/// Newlines in labels are written `\n`, and the shapes that are text boxes are listed in
+/// `[textboxes]` as `id width align padding`. Tables go in the `[tables]` section, as
+/// `id x y rows columns style`, and the text of their cells in the `[tablecells]` section, as
+/// `id row column text`, empty cells being left out. Sequence diagrams go in the `[sequences]`
+/// section, as `id x y style arrowhead source`, newlines in the source being escaped like in
+/// labels. Any file without the header is read as plain text, one row per line, spaces being left
+/// empty, and written back as such.
+#[derive(Default)]
+pub struct Document {
+    pub graphemes: Vec<(Coordinates, String)>,
+    pub marks: BTreeMap<char, Coordinates>,
+    pub objects: Objects,
+    pub format: Format,
+}
+
+/// How a document is written on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Native,
+    /// Only the characters, marks and objects being left out.
+    Plain,
+}
+
+/// The letters of the steps of a path, in the order of [`Direction::ALL`].
+const STEPS: [char; 4] = ['u', 'r', 'd', 'l'];
+
+fn escape_label(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_label(s: &str) -> String {
+    let mut label = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            label.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => label.push('\n'),
+            Some(c) => label.push(c),
+            None => label.push('\\'),
+        }
+    }
+
+    label
+}
+
+fn format_path(path: &[Coordinates]) -> String {
+    let Some(start) = path.first() else {
+        return "-".to_string();
+    };
+
+    let steps = path
+        .windows(2)
+        .filter_map(|w| {
+            Direction::ALL
+                .into_iter()
+                .position(|d| w[0] + d.vector() == w[1])
+                .map(|i| STEPS[i])
+        })
+        .collect::<String>();
+
+    format!("{},{}:{steps}", start.x, start.y)
+}
+
+fn parse_path(s: &str) -> Option<Vec<Coordinates>> {
+    if s == "-" {
+        return Some(Vec::new());
+    }
+
+    let (start, steps) = s.split_once(':')?;
+    let (x, y) = start.split_once(',')?;
+    let mut position = Coordinates::from((x.parse().ok()?, y.parse().ok()?));
+    let mut path = vec![position];
+
+    for step in steps.chars() {
+        let i = STEPS.iter().position(|s| *s == step)?;
+        position += Direction::ALL[i].vector();
+        path.push(position);
+    }
+
+    Some(path)
+}
+
+impl Document {
+    pub fn load(path: &Path) -> Result<Self, DocumentError> {
+        let s = std::fs::read_to_string(path).map_err(|e| DocumentError::Io(path.into(), e))?;
+
+        if s.lines().next() == Some(HEADER) {
+            Self::parse(&s)
+        } else {
+            Ok(Self::from_plain_text(&s))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DocumentError> {
+        let content = match self.format {
+            Format::Native => self.to_string(),
+            Format::Plain => self.to_plain_text(),
+        };
+
+        std::fs::write(path, content).map_err(|e| DocumentError::Io(path.into(), e))
+    }
+
+    pub fn from_plain_text(s: &str) -> Self {
+        let graphemes = s
+            .lines()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                text_area::lay_out(line)
+                    .filter(|(_, g)| *g != " ")
+                    .map(move |(x, g)| (Coordinates::from((x, y as isize)), g.to_string()))
+            })
+            .collect();
+
+        Self {
+            graphemes,
+            format: Format::Plain,
+            ..Default::default()
+        }
+    }
+
+    /// The characters as lines of text, starting from the origin, or further up and left if
+    /// something is drawn there.
+    pub fn to_plain_text(&self) -> String {
+        let left = self
+            .graphemes
+            .iter()
+            .map(|(c, _)| c.x)
+            .min()
+            .unwrap_or(0)
+            .min(0);
+        let mut y = self
+            .graphemes
+            .iter()
+            .map(|(c, _)| c.y)
+            .min()
+            .unwrap_or(0)
+            .min(0);
+        let mut x = left;
+
+        let mut graphemes = self.graphemes.iter().collect::<Vec<_>>();
+        graphemes.sort_by_key(|(c, _)| (c.y, c.x));
+
+        let mut text = String::new();
+
+        for (position, grapheme) in graphemes {
+            while y < position.y {
+                text.push('\n');
+                (x, y) = (left, y + 1);
+            }
+
+            // Covered by a wide character
+            if position.x < x {
+                continue;
+            }
+
+            text.push_str(&" ".repeat((position.x - x) as usize));
+            text.push_str(grapheme);
+            x = position.x + grapheme_width(grapheme) as isize;
+        }
+
+        if !self.graphemes.is_empty() {
+            text.push('\n');
+        }
+
+        text
+    }
+
+    fn parse(s: &str) -> Result<Self, DocumentError> {
+        fn coordinate<'a>(
+            parts: &mut impl Iterator<Item = &'a str>,
+            line: usize,
+        ) -> Result<isize, DocumentError> {
+            parts
+                .next()
+                .and_then(|v| v.parse::<isize>().ok())
+                .ok_or_else(|| DocumentError::Syntax {
+                    line: line + 1,
+                    message: "expected a coordinate".to_string(),
+                })
+        }
+
+        let mut document = Self::default();
+        let mut section = "";
+
+        for (i, line) in s.lines().enumerate().skip(1) {
+            let syntax = |message: &str| DocumentError::Syntax {
+                line: i + 1,
+                message: message.to_string(),
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name;
+                continue;
+            }
+
+            match section {
+                "cells" => {
+                    let mut parts = line.splitn(3, ' ');
+                    let (x, y) = (coordinate(&mut parts, i)?, coordinate(&mut parts, i)?);
+                    let run = parts.next().ok_or_else(|| syntax("expected characters"))?;
+
+                    document.graphemes.extend(
+                        text_area::lay_out(run)
+                            .map(|(i, g)| (Coordinates::from((x + i, y)), g.to_string())),
+                    );
+                }
+                "marks" => {
+                    let mut parts = line.split(' ');
+                    let mut name = parts.next().unwrap_or_default().chars();
+
+                    let (Some(name), None) = (name.next(), name.next()) else {
+                        return Err(syntax("expected a single character mark name"));
+                    };
+                    let (x, y) = (coordinate(&mut parts, i)?, coordinate(&mut parts, i)?);
+
+                    document.marks.insert(name, (x, y).into());
+                }
+                "shapes" => {
+                    let mut parts = line.splitn(7, ' ');
+                    let id = coordinate(&mut parts, i)?;
+                    let (x, y) = (coordinate(&mut parts, i)?, coordinate(&mut parts, i)?);
+                    let (width, height) = (coordinate(&mut parts, i)?, coordinate(&mut parts, i)?);
+                    let style = parts
+                        .next()
+                        .and_then(|s| s.parse::<LineStyle>().ok())
+                        .ok_or_else(|| syntax("expected a line style"))?;
+
+                    document.objects.shapes.insert(
+                        id.try_into().map_err(|_| syntax("invalid shape id"))?,
+                        Shape {
+                            bounds: BoundingBox::new(
+                                (x, y),
+                                width.max(1) as usize,
+                                height.max(1) as usize,
+                            ),
+                            label: unescape_label(parts.next().unwrap_or_default()),
+                            style,
+                            text: None,
+                        },
+                    );
+                }
+                "textboxes" => {
+                    let parts = line.split(' ').collect::<Vec<_>>();
+                    let [id, width, align, padding] = parts[..] else {
+                        return Err(syntax("expected `id width align padding`"));
+                    };
+
+                    let shape = id
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|id| document.objects.shapes.get_mut(&id))
+                        .ok_or_else(|| syntax("unknown shape"))?;
+
+                    shape.text = Some(TextLayout {
+                        width: width.parse().map_err(|_| syntax("invalid width"))?,
+                        align: align.parse().map_err(|_| syntax("invalid alignment"))?,
+                        padding: padding.parse().map_err(|_| syntax("invalid padding"))?,
+                    });
+                }
+                "links" => {
+                    let parts = line.splitn(6, ' ').collect::<Vec<_>>();
+                    let [from, to, style, arrowhead, path, ref label @ ..] = parts[..] else {
+                        return Err(syntax("expected `from to style arrowhead path label`"));
+                    };
+
+                    document.objects.links.push(Link {
+                        from: from
+                            .parse::<Endpoint>()
+                            .map_err(|_| syntax("invalid end"))?,
+                        to: to.parse::<Endpoint>().map_err(|_| syntax("invalid end"))?,
+                        style: style
+                            .parse::<LineStyle>()
+                            .map_err(|_| syntax("invalid line style"))?,
+                        arrowhead: arrowhead
+                            .parse::<Arrowhead>()
+                            .map_err(|_| syntax("invalid arrowhead"))?,
+                        label: unescape_label(label.first().copied().unwrap_or_default()),
+                        path: parse_path(path).ok_or_else(|| syntax("invalid path"))?,
+                    });
+                }
+                "tables" => {
+                    let parts = line.split(' ').collect::<Vec<_>>();
+                    let [id, x, y, rows, columns, style] = parts[..] else {
+                        return Err(syntax("expected `id x y rows columns style`"));
+                    };
+                    let count = |v: &str| match v.parse::<usize>() {
+                        Ok(n) if n > 0 => Ok(n),
+                        _ => Err(syntax("invalid count")),
+                    };
+
+                    document.objects.tables.insert(
+                        id.parse().map_err(|_| syntax("invalid table id"))?,
+                        Table::new(
+                            (
+                                x.parse().map_err(|_| syntax("invalid coordinate"))?,
+                                y.parse().map_err(|_| syntax("invalid coordinate"))?,
+                            )
+                                .into(),
+                            count(rows)?,
+                            count(columns)?,
+                            style.parse().map_err(|_| syntax("invalid line style"))?,
+                        ),
+                    );
+                }
+                "tablecells" => {
+                    let mut parts = line.splitn(4, ' ');
+                    let mut index = || parts.next().and_then(|v| v.parse::<usize>().ok());
+
+                    let (Some(id), Some(row), Some(column)) = (index(), index(), index()) else {
+                        return Err(syntax("expected `id row column text`"));
+                    };
+                    let cell = document
+                        .objects
+                        .tables
+                        .get_mut(&id)
+                        .and_then(|t| t.cells.get_mut(row))
+                        .and_then(|r| r.get_mut(column))
+                        .ok_or_else(|| syntax("unknown table cell"))?;
+
+                    *cell = unescape_label(parts.next().unwrap_or_default());
+                }
+                "sequences" => {
+                    let mut parts = line.splitn(6, ' ');
+                    let id = coordinate(&mut parts, i)?;
+                    let (x, y) = (coordinate(&mut parts, i)?, coordinate(&mut parts, i)?);
+                    let style = parts
+                        .next()
+                        .and_then(|s| s.parse::<LineStyle>().ok())
+                        .ok_or_else(|| syntax("expected a line style"))?;
+                    let arrowhead = parts
+                        .next()
+                        .and_then(|s| s.parse::<Arrowhead>().ok())
+                        .ok_or_else(|| syntax("expected an arrowhead"))?;
+                    let source = unescape_label(parts.next().unwrap_or_default());
+
+                    sequence::check(&source).map_err(|e| syntax(&e))?;
+                    document.objects.sequences.insert(
+                        id.try_into()
+                            .map_err(|_| syntax("invalid sequence diagram id"))?,
+                        Sequence {
+                            origin: (x, y).into(),
+                            source,
+                            style,
+                            arrowhead,
+                        },
+                    );
+                }
+                _ => return Err(syntax(&format!("unknown section `{section}`"))),
+            }
+        }
+
+        Ok(document)
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "[cells]")?;
+
+        let rows = self.graphemes.iter().fold(
+            BTreeMap::<isize, BTreeMap<isize, &str>>::new(),
+            |mut rows, (coords, g)| {
+                rows.entry(coords.y).or_default().insert(coords.x, g);
+                rows
+            },
+        );
+
+        for (y, row) in rows {
+            let mut run: Option<(isize, String)> = None;
+            let mut next_x = None;
+
+            for (x, g) in row {
+                if next_x != Some(x) {
+                    if let Some((start, s)) = run.take() {
+                        writeln!(f, "{start} {y} {s}")?;
+                    }
+                }
+
+                run.get_or_insert_with(|| (x, String::new())).1.push_str(g);
+                next_x = Some(x + grapheme_width(g) as isize);
+            }
+
+            if let Some((start, s)) = run {
+                writeln!(f, "{start} {y} {s}")?;
+            }
+        }
+
+        if !self.marks.is_empty() {
+            writeln!(f, "[marks]")?;
+
+            for (name, position) in &self.marks {
+                writeln!(f, "{name} {} {}", position.x, position.y)?;
+            }
+        }
+
+        if !self.objects.shapes.is_empty() {
+            writeln!(f, "[shapes]")?;
+
+            for (id, shape) in &self.objects.shapes {
+                let bounds = shape.bounds;
+                writeln!(
+                    f,
+                    "{id} {} {} {} {} {} {}",
+                    bounds.top_left.x,
+                    bounds.top_left.y,
+                    bounds.width,
+                    bounds.height,
+                    shape.style,
+                    escape_label(&shape.label),
+                )?;
+            }
+        }
+
+        let text_boxes = self
+            .objects
+            .shapes
+            .iter()
+            .filter_map(|(id, shape)| shape.text.map(|layout| (id, layout)))
+            .collect::<Vec<_>>();
+
+        if !text_boxes.is_empty() {
+            writeln!(f, "[textboxes]")?;
+
+            for (id, layout) in text_boxes {
+                writeln!(
+                    f,
+                    "{id} {} {} {}",
+                    layout.width, layout.align, layout.padding
+                )?;
+            }
+        }
+
+        if !self.objects.links.is_empty() {
+            writeln!(f, "[links]")?;
+
+            for link in &self.objects.links {
+                write!(
+                    f,
+                    "{} {} {} {} {}",
+                    link.from,
+                    link.to,
+                    link.style,
+                    link.arrowhead,
+                    format_path(&link.path),
+                )?;
+
+                match link.label.is_empty() {
+                    true => writeln!(f)?,
+                    false => writeln!(f, " {}", escape_label(&link.label))?,
+                }
+            }
+        }
+
+        if !self.objects.tables.is_empty() {
+            writeln!(f, "[tables]")?;
+
+            for (id, table) in &self.objects.tables {
+                writeln!(
+                    f,
+                    "{id} {} {} {} {} {}",
+                    table.top_left.x,
+                    table.top_left.y,
+                    table.rows(),
+                    table.columns(),
+                    table.style,
+                )?;
+            }
+
+            writeln!(f, "[tablecells]")?;
+
+            for (id, table) in &self.objects.tables {
+                for (r, row) in table.cells.iter().enumerate() {
+                    for (c, text) in row.iter().enumerate().filter(|(_, t)| !t.is_empty()) {
+                        writeln!(f, "{id} {r} {c} {}", escape_label(text))?;
+                    }
+                }
+            }
+        }
+
+        if !self.objects.sequences.is_empty() {
+            writeln!(f, "[sequences]")?;
+
+            for (id, sequence) in &self.objects.sequences {
+                writeln!(
+                    f,
+                    "{id} {} {} {} {} {}",
+                    sequence.origin.x,
+                    sequence.origin.y,
+                    sequence.style,
+                    sequence.arrowhead,
+                    escape_label(&sequence.source),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Align;
+
+    #[test]
+    fn save_and_load() {
+        let mut objects = Objects::default();
+        objects.shapes.insert(
+            0,
+            Shape {
+                bounds: BoundingBox::new((1, 2), 10, 4),
+                label: "two words \\ and\na new line".to_string(),
+                style: LineStyle::Rounded,
+                text: None,
+            },
+        );
+        objects.shapes.insert(
+            3,
+            Shape {
+                bounds: BoundingBox::new((20, 2), 8, 3),
+                label: "text box".to_string(),
+                style: LineStyle::Light,
+                text: Some(TextLayout {
+                    width: 12,
+                    align: Align::Right,
+                    padding: 2,
+                }),
+            },
+        );
+        objects.links.push(Link {
+            from: Endpoint::Side(0, Direction::Right),
+            to: Endpoint::Side(3, Direction::Left),
+            style: LineStyle::Heavy,
+            arrowhead: Arrowhead::Triangle,
+            label: "a link\\label".to_string(),
+            path: vec![(11, 3).into(), (12, 3).into(), (12, 4).into()],
+        });
+        objects.links.push(Link {
+            from: Endpoint::Cell((0, 9).into()),
+            to: Endpoint::Side(0, Direction::Down),
+            style: LineStyle::Ascii,
+            arrowhead: Arrowhead::None,
+            label: String::new(),
+            path: Vec::new(),
+        });
+
+        let mut table = Table::new((0, 12).into(), 2, 3, LineStyle::Double);
+        table.cells[0][1] = "a cell".to_string();
+        table.cells[1][2] = "two\nlines".to_string();
+        objects.tables.insert(1, table);
+
+        objects.sequences.insert(
+            0,
+            Sequence {
+                origin: (30, 0).into(),
+                source: "a -> b: hello\nb --> a".to_string(),
+                style: LineStyle::Light,
+                arrowhead: Arrowhead::Arrow,
+            },
+        );
+
+        let document = Document {
+            graphemes: [((0, 0), "a"), ((1, 0), "世"), ((3, 0), "b"), ((5, 0), "c")]
+                .into_iter()
+                .map(|(position, g)| (position.into(), g.to_string()))
+                .collect(),
+            marks: BTreeMap::from([('a', (4, 5).into())]),
+            objects,
+            format: Format::Native,
+        };
+
+        let path = std::env::temp_dir().join(format!("boxdrawed-{}.txt", std::process::id()));
+        document.save(&path).unwrap();
+        let loaded = Document::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.graphemes, document.graphemes);
+        assert_eq!(loaded.marks, document.marks);
+        assert_eq!(loaded.objects, document.objects);
+    }
+
+    #[test]
+    fn plain_text() {
+        for text in ["", "a\n", "\n  ┌─┐\n\n世界 x\n", "a  b\n c\n"] {
+            let document = Document::from_plain_text(text);
+
+            assert_eq!(document.format, Format::Plain);
+            assert_eq!(document.to_plain_text(), text, "{text:?}");
+        }
+    }
+}
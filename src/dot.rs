@@ -0,0 +1,654 @@
+//! Imports graphs written in a subset of the Graphviz DOT language: node and edge statements with
+//! their `label` attributes, edge chains like `a -> b -> c`, subgraphs, whose content is merged
+//! into the graph, and the `rankdir` attribute. Other attributes are read and left out.
+//!
+//! Nodes are laid out in layers following the edges, each node going one layer after the furthest
+//! of the nodes it comes from, and ordered in their layer so that edges cross as little as
+//! possible. Nodes become text boxes and edges links between their sides.
+
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use crate::{
+    glyphs::{Arrowhead, LineStyle},
+    objects::{Align, Endpoint, Link, Objects, Shape, TextLayout},
+    text_area::{self, BoundingBox, Direction},
+};
+
+/// The blank cells between two layers, leaving room for the edges going across.
+const LAYER_GAP: usize = 5;
+/// The blank cells between two nodes of the same layer.
+const NODE_GAP: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Id(String),
+    Edge,
+    Symbol(char),
+}
+
+/// Splits DOT source into tokens along with their line numbers, leaving out comments.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '/' if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            '/' if chars.next_if_eq(&'*').is_some() => loop {
+                match chars.next() {
+                    Some('*') if chars.next_if_eq(&'/').is_some() => break,
+                    Some('\n') => line += 1,
+                    Some(_) => {}
+                    None => return Err(format!("line {line}: unterminated comment")),
+                }
+            },
+            '-' if chars.next_if(|c| *c == '>' || *c == '-').is_some() => {
+                tokens.push((line, Token::Edge))
+            }
+            '"' => {
+                let mut id = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n' | 'l' | 'r') => id.push('\n'),
+                            Some('\n') => line += 1,
+                            Some(c) => id.push(c),
+                            None => {}
+                        },
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            id.push(c);
+                        }
+                        None => return Err(format!("line {line}: unterminated string")),
+                    }
+                }
+
+                tokens.push((line, Token::Id(id.trim_end_matches('\n').to_string())));
+            }
+            '{' | '}' | '[' | ']' | ';' | ',' | '=' | ':' => tokens.push((line, Token::Symbol(c))),
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let mut id = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    id.push(c);
+                }
+
+                tokens.push((line, Token::Id(id)));
+            }
+            c => return Err(format!("line {line}: unexpected `{c}`")),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Default)]
+pub struct Graph {
+    directed: bool,
+    /// Whether layers go from left to right instead of from top to bottom.
+    left_to_right: bool,
+    /// The identifier and label of every node, in the order they appear in.
+    nodes: Vec<(String, String)>,
+    /// The nodes every edge goes from and to, and its label.
+    edges: Vec<(usize, usize, String)>,
+}
+
+impl Graph {
+    fn node(&mut self, id: &str) -> usize {
+        match self.nodes.iter().position(|(n, _)| n == id) {
+            Some(i) => i,
+            None => {
+                self.nodes.push((id.to_string(), id.to_string()));
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, t)| t)
+    }
+
+    fn error(&self, message: &str) -> String {
+        match self.tokens.get(self.position).or(self.tokens.last()) {
+            Some((line, _)) => format!("line {line}: {message}"),
+            None => message.to_string(),
+        }
+    }
+
+    fn next_id(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Id(id)) => {
+                let id = id.clone();
+                self.position += 1;
+                Ok(id)
+            }
+            _ => Err(self.error("expected an identifier")),
+        }
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        self.position += found as usize;
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.eat(symbol) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected `{symbol}`"))),
+        }
+    }
+
+    /// Reads the lists of `[name = value, …]` attributes following a statement.
+    fn attributes(&mut self) -> Result<BTreeMap<String, String>, String> {
+        let mut attributes = BTreeMap::new();
+
+        while self.eat('[') {
+            while !self.eat(']') {
+                let name = self.next_id()?;
+                self.expect('=')?;
+                attributes.insert(name, self.next_id()?);
+
+                if !self.eat(',') {
+                    self.eat(';');
+                }
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Reads a node identifier, leaving out its port if any.
+    fn node_id(&mut self) -> Result<String, String> {
+        let id = self.next_id()?;
+
+        while self.eat(':') {
+            self.next_id()?;
+        }
+
+        Ok(id)
+    }
+
+    /// Reads statements up to the closing brace of the current graph or subgraph.
+    fn statements(&mut self, graph: &mut Graph) -> Result<(), String> {
+        while !self.eat('}') {
+            match self.peek() {
+                None => return Err(self.error("expected `}`")),
+                Some(Token::Symbol(';')) => self.position += 1,
+                Some(Token::Symbol('{')) => {
+                    self.position += 1;
+                    self.statements(graph)?;
+                }
+                Some(Token::Id(id)) if id == "subgraph" => {
+                    self.position += 1;
+
+                    if !self.eat('{') {
+                        self.next_id()?;
+                        self.expect('{')?;
+                    }
+
+                    self.statements(graph)?;
+                }
+                Some(Token::Id(id)) if ["graph", "node", "edge"].contains(&id.as_str()) => {
+                    let is_graph = id == "graph";
+                    self.position += 1;
+
+                    let attributes = self.attributes()?;
+
+                    if is_graph && attributes.get("rankdir").is_some_and(|d| d == "LR") {
+                        graph.left_to_right = true;
+                    }
+                }
+                _ => {
+                    let first = self.node_id()?;
+
+                    if self.eat('=') {
+                        let value = self.next_id()?;
+
+                        if first == "rankdir" {
+                            graph.left_to_right = value == "LR";
+                        }
+
+                        continue;
+                    }
+
+                    let mut chain = vec![first];
+
+                    while self.peek() == Some(&Token::Edge) {
+                        self.position += 1;
+                        chain.push(self.node_id()?);
+                    }
+
+                    let attributes = self.attributes()?;
+                    let label = attributes.get("label").cloned();
+
+                    if let [id] = &chain[..] {
+                        let node = graph.node(id);
+
+                        if let Some(label) = label {
+                            graph.nodes[node].1 = label;
+                        }
+                    } else {
+                        for pair in chain.windows(2) {
+                            let (from, to) = (graph.node(&pair[0]), graph.node(&pair[1]));
+                            graph
+                                .edges
+                                .push((from, to, label.clone().unwrap_or_default()));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a graph written in DOT, failing with the line of the first error.
+pub fn parse(source: &str) -> Result<Graph, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    let mut graph = Graph::default();
+
+    if parser.peek() == Some(&Token::Id("strict".to_string())) {
+        parser.position += 1;
+    }
+
+    graph.directed = match parser.next_id()?.as_str() {
+        "digraph" => true,
+        "graph" => false,
+        _ => return Err(parser.error("expected `graph` or `digraph`")),
+    };
+
+    if !parser.eat('{') {
+        parser.next_id()?;
+        parser.expect('{')?;
+    }
+
+    parser.statements(&mut graph)?;
+
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected content after the graph"));
+    }
+
+    Ok(graph)
+}
+
+/// The layer of every node, cycles being broken by leaving out the edges going back to a node
+/// being visited.
+fn layers(graph: &Graph) -> Vec<usize> {
+    let count = graph.nodes.len();
+    let mut successors = vec![Vec::new(); count];
+
+    for (from, to, _) in &graph.edges {
+        if from != to {
+            successors[*from].push(*to);
+        }
+    }
+
+    // Depth first, keeping the nodes in the order they are finished in
+    let mut state = vec![0u8; count];
+    let mut finished = Vec::with_capacity(count);
+    let mut forward = vec![Vec::new(); count];
+
+    for root in 0..count {
+        if state[root] != 0 {
+            continue;
+        }
+
+        let mut stack = vec![(root, 0)];
+        state[root] = 1;
+
+        while let Some((node, next)) = stack.pop() {
+            match successors[node].get(next) {
+                Some(&successor) => {
+                    stack.push((node, next + 1));
+
+                    match state[successor] {
+                        0 => {
+                            state[successor] = 1;
+                            forward[node].push(successor);
+                            stack.push((successor, 0));
+                        }
+                        2 => forward[node].push(successor),
+                        // Back to a node being visited
+                        _ => {}
+                    }
+                }
+                None => {
+                    state[node] = 2;
+                    finished.push(node);
+                }
+            }
+        }
+    }
+
+    let mut layers = vec![0; count];
+
+    for &node in finished.iter().rev() {
+        for &successor in &forward[node] {
+            layers[successor] = layers[successor].max(layers[node] + 1);
+        }
+    }
+
+    layers
+}
+
+/// Orders the nodes of every layer by the average position of their neighbours in the layer
+/// before, then in the layer after, a few times over.
+fn order(graph: &Graph, layers: &[usize]) -> Vec<Vec<usize>> {
+    let mut ordered = vec![Vec::new(); layers.iter().max().map_or(0, |l| l + 1)];
+
+    for (node, layer) in layers.iter().enumerate() {
+        ordered[*layer].push(node);
+    }
+
+    for sweep in 0..4 {
+        let downwards = sweep % 2 == 0;
+        let mut position = vec![0.0; graph.nodes.len()];
+
+        for layer in &ordered {
+            for (i, node) in layer.iter().enumerate() {
+                position[*node] = i as f32;
+            }
+        }
+
+        let indices = (0..ordered.len()).collect::<Vec<_>>();
+        let indices = match downwards {
+            true => indices,
+            false => indices.into_iter().rev().collect(),
+        };
+
+        for i in indices {
+            let neighbour_layer = match downwards {
+                true => i.checked_sub(1),
+                false => Some(i + 1).filter(|l| *l < ordered.len()),
+            };
+            let Some(neighbour_layer) = neighbour_layer else {
+                continue;
+            };
+
+            let key = |node: usize| {
+                let neighbours = graph
+                    .edges
+                    .iter()
+                    .filter_map(|(from, to, _)| match (*from == node, *to == node) {
+                        (true, false) => Some(*to),
+                        (false, true) => Some(*from),
+                        _ => None,
+                    })
+                    .filter(|n| layers[*n] == neighbour_layer)
+                    .map(|n| position[n])
+                    .collect::<Vec<_>>();
+
+                match neighbours.is_empty() {
+                    true => position[node],
+                    false => neighbours.iter().sum::<f32>() / neighbours.len() as f32,
+                }
+            };
+
+            let mut keyed = ordered[i].iter().map(|n| (key(*n), *n)).collect::<Vec<_>>();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            ordered[i] = keyed.into_iter().map(|(_, n)| n).collect();
+
+            for (j, node) in ordered[i].iter().enumerate() {
+                position[*node] = j as f32;
+            }
+        }
+    }
+
+    ordered
+}
+
+/// Lays the graph out as objects, the first layer starting at the top left of the grid.
+pub fn layout(graph: &Graph, style: LineStyle, arrowhead: Arrowhead) -> Objects {
+    let layers = layers(graph);
+    let ordered = order(graph, &layers);
+
+    let (forwards, backwards, across) = match graph.left_to_right {
+        false => (Direction::Down, Direction::Up, Direction::Right),
+        true => (Direction::Right, Direction::Left, Direction::Down),
+    };
+    let position = |n: usize| ordered[layers[n]].iter().position(|m| *m == n);
+
+    let sides = graph
+        .edges
+        .iter()
+        .map(|(from, to, _)| match layers[*from].cmp(&layers[*to]) {
+            Ordering::Less => (forwards, backwards),
+            // Edges going back leave and enter by the side, clear of those going forwards
+            Ordering::Greater => (across, across),
+            // Nodes of the same layer are joined by their sides facing each other
+            Ordering::Equal => match position(*from) <= position(*to) {
+                true if from != to => (across, across.opposite()),
+                _ => (across.opposite(), across),
+            },
+        })
+        .collect::<Vec<_>>();
+
+    // How many links are attached to every side of every node
+    let mut attached = vec![[0usize; 4]; graph.nodes.len()];
+
+    for ((from, to, _), (start, end)) in graph.edges.iter().zip(&sides) {
+        attached[*from][*start as usize] += 1;
+        attached[*to][*end as usize] += 1;
+    }
+
+    let sizes = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(node, (_, label))| {
+            let width = label.lines().map(text_area::columns).max().unwrap_or(0);
+            let height = label.lines().count().max(1) + 2;
+
+            // Wide enough to keep two cells between the links on its top and bottom sides
+            let links = attached[node][Direction::Up as usize]
+                .max(attached[node][Direction::Down as usize]);
+            let padding = (3 * links + 1).saturating_sub(width.max(1) + 2).div_ceil(2);
+
+            (
+                width.max(1) + 2 * padding.max(1) + 2,
+                height,
+                padding.max(1),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Along layers and across them, whichever way they go
+    let extent = |node: usize| match graph.left_to_right {
+        false => (sizes[node].0, sizes[node].1),
+        true => (sizes[node].1, sizes[node].0),
+    };
+    let breadth =
+        |layer: &[usize]| layer.iter().map(|n| extent(*n).0 + NODE_GAP).sum::<usize>() - NODE_GAP;
+    let widest = ordered.iter().map(|l| breadth(l)).max().unwrap_or(0);
+
+    let mut objects = Objects::default();
+    let mut depth = 0;
+
+    for layer in &ordered {
+        let mut along = (widest - breadth(layer)) / 2;
+
+        for &node in layer {
+            let (x, y) = match graph.left_to_right {
+                false => (along, depth),
+                true => (depth, along),
+            };
+            let (width, height, padding) = sizes[node];
+
+            objects.shapes.insert(
+                node,
+                Shape {
+                    bounds: BoundingBox::new((x as isize, y as isize), width, height),
+                    label: graph.nodes[node].1.clone(),
+                    style,
+                    text: Some(TextLayout {
+                        width: width - 2 * padding - 2,
+                        align: Align::Center,
+                        padding,
+                    }),
+                },
+            );
+
+            along += extent(node).0 + NODE_GAP;
+        }
+
+        depth += layer.iter().map(|n| extent(*n).1).max().unwrap_or(0) + LAYER_GAP;
+    }
+
+    // Links are routed in order, so those across a single layer go first, and those going
+    // back last, around everything else
+    let mut edges = graph.edges.iter().zip(sides).collect::<Vec<_>>();
+    edges.sort_by_key(|((from, to, _), _)| match layers[*from] <= layers[*to] {
+        true => layers[*to] - layers[*from],
+        false => usize::MAX,
+    });
+
+    for ((from, to, label), (start, end)) in edges {
+        objects.links.push(Link {
+            from: Endpoint::Side(*from, start),
+            to: Endpoint::Side(*to, end),
+            style,
+            arrowhead: match graph.directed {
+                true => arrowhead,
+                false => Arrowhead::None,
+            },
+            label: label.clone(),
+            path: Vec::new(),
+        });
+    }
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(graph: &Graph) -> Vec<(&str, &str, &str)> {
+        graph
+            .edges
+            .iter()
+            .map(|(from, to, label)| {
+                (
+                    graph.nodes[*from].0.as_str(),
+                    graph.nodes[*to].0.as_str(),
+                    label.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_chains() {
+        let graph = parse("digraph G { a -> b -> c [label=\"x\"]; c -> a }").unwrap();
+
+        assert!(graph.directed);
+        assert_eq!(
+            edges(&graph),
+            [("a", "b", "x"), ("b", "c", "x"), ("c", "a", "")]
+        );
+    }
+
+    #[test]
+    fn parse_subgraphs() {
+        let graph =
+            parse("strict graph { subgraph cluster_0 { a; b -- c } { d } a -- d }").unwrap();
+
+        assert!(!graph.directed);
+        assert_eq!(
+            graph
+                .nodes
+                .iter()
+                .map(|(id, _)| id.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b", "c", "d"]
+        );
+        assert_eq!(edges(&graph), [("b", "c", ""), ("a", "d", "")]);
+    }
+
+    #[test]
+    fn parse_rankdir() {
+        for (source, left_to_right) in [
+            ("digraph { a -> b }", false),
+            ("digraph { rankdir=LR; a -> b }", true),
+            ("digraph { rankdir = TB }", false),
+            ("digraph { graph [rankdir=LR] }", true),
+            ("digraph { node [shape=box] edge [rankdir=LR] }", false),
+        ] {
+            assert_eq!(
+                parse(source).unwrap().left_to_right,
+                left_to_right,
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_comments() {
+        let source = "// a -> b\ndigraph {\n# c -> d\n/* e -> f\n */ g -> h // i -> j\n}";
+        let graph = parse(source).unwrap();
+
+        assert_eq!(edges(&graph), [("g", "h", "")]);
+    }
+
+    #[test]
+    fn parse_labels() {
+        let graph =
+            parse("digraph { a [label=\"one\\ntwo\\l\"]; \"b c\" [label=\"\\\"q\\\"\"] }").unwrap();
+
+        assert_eq!(
+            graph.nodes,
+            [
+                ("a".to_string(), "one\ntwo".to_string()),
+                ("b c".to_string(), "\"q\"".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (source, error) in [
+            ("", "expected an identifier"),
+            ("tree { }", "line 1: expected `graph` or `digraph`"),
+            ("digraph {\n a ->\n}", "line 3: expected an identifier"),
+            ("digraph {\n a [label=x\n b", "line 3: expected `=`"),
+            ("digraph {\n\n a [label=\"x", "line 3: unterminated string"),
+            ("digraph {\n /* a\n\n", "line 4: unterminated comment"),
+            ("digraph {\n a\n", "line 2: expected `}`"),
+            (
+                "digraph { a }\n}",
+                "line 2: unexpected content after the graph",
+            ),
+            ("digraph {\n a @ b }", "line 2: unexpected `@`"),
+        ] {
+            assert_eq!(parse(source).unwrap_err(), error, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn layers_of_a_cycle() {
+        let graph = parse("digraph { a -> b -> c -> a; c -> d; d -> d }").unwrap();
+
+        assert_eq!(layers(&graph), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Drawing primitives writing box drawing glyphs into a [`TextArea`], merging them with the lines
+//! already there.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    glyphs::{self, Arrowhead, Connections, LineStyle},
+    route,
+    text_area::{BoundingBox, Cell, Coordinates, Direction, TextArea},
+};
+
+/// The most cells a fill may cover, as nothing else stops it from spreading over the whole grid.
+pub const FILL_LIMIT: usize = 10_000;
+
+/// What to do with the inside of a rectangle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RectangleFill {
+    /// Leave the inside as it is.
+    Outline,
+    /// Erase everything inside.
+    Clear,
+    /// Cover the inside with a character.
+    Fill(char),
+}
+
+/// The connections of the glyphs around `position` that have a line going towards it.
+pub fn neighbour_connections(area: &TextArea, position: Coordinates) -> Connections {
+    Direction::ALL
+        .into_iter()
+        .filter(|direction| {
+            area.char_at(position + direction.vector())
+                .and_then(glyphs::connections)
+                .is_some_and(|(c, _)| c & glyphs::towards(direction.opposite()) != 0)
+        })
+        .fold(0, |connections, direction| {
+            connections | glyphs::towards(direction)
+        })
+}
+
+/// Draws a glyph with `connections` at `position`, joined with the lines ending next to it.
+pub fn connect(
+    area: &mut TextArea,
+    position: Coordinates,
+    connections: Connections,
+    style: LineStyle,
+) {
+    let connections = connections | neighbour_connections(area, position);
+    area.write_at(position, glyphs::glyph(connections, style));
+}
+
+/// Draws the outline of `bounding_box`, turning the lines it crosses into junctions, as a single
+/// undo step.
+pub fn rectangle(
+    area: &mut TextArea,
+    bounding_box: BoundingBox,
+    style: LineStyle,
+    fill: RectangleFill,
+) {
+    let top_left = bounding_box.top_left;
+    let bottom_right = bounding_box.bottom_right();
+
+    area.begin_undo_group();
+
+    // The inside goes first so that the outline only joins the lines that are still there.
+    for y in top_left.y + 1..bottom_right.y {
+        for x in top_left.x + 1..bottom_right.x {
+            match fill {
+                RectangleFill::Outline => {}
+                RectangleFill::Clear => area.erase_at((x, y).into()),
+                RectangleFill::Fill(c) => area.write_at((x, y).into(), c),
+            }
+        }
+    }
+
+    for y in top_left.y..=bottom_right.y {
+        for x in top_left.x..=bottom_right.x {
+            let mut connections = 0;
+
+            if y == top_left.y || y == bottom_right.y {
+                if x > top_left.x {
+                    connections |= glyphs::LEFT;
+                }
+                if x < bottom_right.x {
+                    connections |= glyphs::RIGHT;
+                }
+            }
+
+            if x == top_left.x || x == bottom_right.x {
+                if y > top_left.y {
+                    connections |= glyphs::UP;
+                }
+                if y < bottom_right.y {
+                    connections |= glyphs::DOWN;
+                }
+            }
+
+            if connections != 0 {
+                connect(area, (x, y).into(), connections, style);
+            }
+        }
+    }
+
+    area.end_undo_group();
+}
+
+/// The direction of the step from `from` to the orthogonally adjacent `to`.
+fn step(from: Coordinates, to: Coordinates) -> Direction {
+    Direction::ALL
+        .into_iter()
+        .find(|d| from + d.vector() == to)
+        .unwrap()
+}
+
+/// Draws a connector routed around the characters between `start` and `end` and through none of
+/// `avoid`, as a single undo step. The connector is joined to the lines at `start`, and ends with
+/// an arrowhead at `end`, or right before it if something is drawn there already. Without an
+/// arrowhead, it is joined to the lines at `end` too. Returns the cells drawn over, leaving out
+/// the ends that weren't blank, or `None` if there is no route.
+pub fn connector(
+    area: &mut TextArea,
+    start: Coordinates,
+    end: Coordinates,
+    style: LineStyle,
+    arrowhead: Arrowhead,
+    avoid: &[BoundingBox],
+) -> Option<Vec<Coordinates>> {
+    let mut path = route::route(area, start, end, avoid)?;
+    let mut heading = None;
+
+    let joined_end = !area.is_blank(&end) && arrowhead == Arrowhead::None;
+
+    if path.len() > 2 && !area.is_blank(&end) && arrowhead != Arrowhead::None {
+        path.pop();
+        heading = Some(step(path[path.len() - 1], end));
+    }
+
+    let joined = !area.is_blank(&start);
+    area.begin_undo_group();
+
+    for (i, position) in path.iter().enumerate() {
+        let before = i.checked_sub(1).map(|i| step(*position, path[i]));
+        let after = path.get(i + 1).map(|next| step(*position, *next));
+
+        match (before, after) {
+            (None, Some(after)) => connect(area, *position, glyphs::towards(after), style),
+            (Some(before), Some(after)) => area.write_at(
+                *position,
+                glyphs::glyph(glyphs::towards(before) | glyphs::towards(after), style),
+            ),
+            (Some(before), None) if arrowhead == Arrowhead::None => {
+                connect(area, *position, glyphs::towards(before), style)
+            }
+            (Some(before), None) => area.write_at(
+                *position,
+                arrowhead.glyph(heading.unwrap_or(before.opposite())),
+            ),
+            (None, None) => {}
+        }
+    }
+
+    area.end_undo_group();
+
+    if joined_end {
+        path.pop();
+    }
+
+    if joined && !path.is_empty() {
+        path.remove(0);
+    }
+
+    Some(path)
+}
+
+/// The 4-connected cells around `start` for which `inside` holds, or `None` if there are more
+/// than [`FILL_LIMIT`] of them.
+fn region<F>(area: &TextArea, start: Coordinates, inside: F) -> Option<Vec<Coordinates>>
+where
+    F: Fn(Coordinates) -> bool,
+{
+    let mut cells = Vec::new();
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(position) = queue.pop_front() {
+        if !inside(position) {
+            continue;
+        }
+
+        if cells.len() == FILL_LIMIT {
+            return None;
+        }
+
+        cells.push(position);
+
+        for direction in Direction::ALL {
+            let next = position + direction.vector();
+
+            if seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    // Wide characters only take the cell they start in
+    cells.retain(|position| !matches!(area.cell_at(*position), Some(Cell::Continuation)));
+    Some(cells)
+}
+
+/// Covers the cells around `start` that are blank, or hold what is drawn at `start`, with `c`, as
+/// a single undo step. Returns how many cells were filled, or `None` if there are too many.
+pub fn fill(area: &mut TextArea, start: Coordinates, c: char) -> Option<usize> {
+    let target = area.grapheme_at(start).map(str::to_string);
+    let cells = region(area, start, |position| match &target {
+        Some(g) if g != " " => area.grapheme_at(position) == Some(g.as_str()),
+        _ => area.is_blank(&position),
+    })?;
+
+    area.begin_undo_group();
+
+    for position in &cells {
+        area.write_at(*position, c);
+    }
+
+    area.end_undo_group();
+    Some(cells.len())
+}
+
+/// Erases everything within the box drawing lines enclosing `start`, as a single undo step.
+/// Returns how many cells were erased, or `None` if the lines don't close around `start` soon
+/// enough.
+pub fn clear_region(area: &mut TextArea, start: Coordinates) -> Option<usize> {
+    let cells = region(area, start, |position| {
+        area.char_at(position)
+            .and_then(glyphs::connections)
+            .is_none()
+    })?;
+
+    let cells = cells
+        .into_iter()
+        .filter(|position| !area.is_blank(position))
+        .collect::<Vec<_>>();
+
+    area.begin_undo_group();
+
+    for position in &cells {
+        area.erase_at(*position);
+    }
+
+    area.end_undo_group();
+    Some(cells.len())
+}
@@ -0,0 +1,128 @@
+use std::{fmt, str::FromStr};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    canvas::Color,
+    text_area::{grapheme_width, Coordinates},
+    theme::Theme,
+};
+
+/// The size of a cell in exported SVG documents, in user units.
+const SVG_CELL_WIDTH: f32 = 9.6;
+const SVG_CELL_HEIGHT: f32 = 19.2;
+const SVG_FONT_SIZE: f32 = 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Text,
+    Svg,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Text, ExportFormat::Svg];
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Text => write!(f, "txt"),
+            ExportFormat::Svg => write!(f, "svg"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.to_string() == s)
+            .ok_or(())
+    }
+}
+
+/// Lays the graphemes out as lines of text, starting from the top left of their bounding box.
+fn rows<'a, I>(characters: I) -> Vec<String>
+where
+    I: IntoIterator<Item = (Coordinates, &'a str)>,
+{
+    let characters = characters.into_iter().collect::<Vec<_>>();
+
+    let Some(min_x) = characters.iter().map(|(c, _)| c.x).min() else {
+        return Vec::new();
+    };
+    let min_y = characters.iter().map(|(c, _)| c.y).min().unwrap();
+    let max_y = characters.iter().map(|(c, _)| c.y).max().unwrap();
+
+    let mut rows = vec![Vec::new(); (max_y - min_y + 1) as usize];
+
+    for (coords, g) in characters {
+        let row = &mut rows[(coords.y - min_y) as usize];
+        let x = (coords.x - min_x) as usize;
+        let width = grapheme_width(g);
+
+        if row.len() < x + width {
+            row.resize(x + width, " ");
+        }
+
+        row[x] = g;
+
+        // The right half of wide characters is covered by their left half.
+        if width == 2 {
+            row[x + 1] = "";
+        }
+    }
+
+    rows.into_iter()
+        .map(|row| row.concat().trim_end().to_string())
+        .collect()
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn export<'a, I>(format: ExportFormat, characters: I, theme: &Theme) -> String
+where
+    I: IntoIterator<Item = (Coordinates, &'a str)>,
+{
+    let rows = rows(characters);
+
+    match format {
+        ExportFormat::Text => rows.into_iter().map(|r| r + "\n").collect(),
+        ExportFormat::Svg => {
+            let columns = rows.iter().map(|r| r.width()).max().unwrap_or(0);
+            let width = columns as f32 * SVG_CELL_WIDTH;
+            let height = rows.len() as f32 * SVG_CELL_HEIGHT;
+
+            let mut svg = format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+                 viewBox=\"0 0 {width} {height}\">\n\
+                 <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n\
+                 <g font-family=\"monospace\" font-size=\"{SVG_FONT_SIZE}\" fill=\"{}\" \
+                 xml:space=\"preserve\">\n",
+                hex(theme.background),
+                hex(theme.foreground),
+            );
+
+            for (i, row) in rows.iter().enumerate().filter(|(_, r)| !r.is_empty()) {
+                svg += &format!(
+                    "<text x=\"0\" y=\"{}\" textLength=\"{}\">{}</text>\n",
+                    (i as f32 + 0.8) * SVG_CELL_HEIGHT,
+                    row.width() as f32 * SVG_CELL_WIDTH,
+                    escape_xml(row),
+                );
+            }
+
+            svg + "</g>\n</svg>\n"
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use lazy_static::lazy_static;
+
+use crate::text_area::Direction;
+
+/// The set of directions a box drawing glyph has a line going towards.
+///
+/// Bit 0 is up, bit 1 is right, bit 2 is down and bit 3 is left.
+pub type Connections = u8;
+
+pub const UP: Connections = 0b0001;
+pub const RIGHT: Connections = 0b0010;
+pub const DOWN: Connections = 0b0100;
+pub const LEFT: Connections = 0b1000;
+
+/// The connection of a line going towards `direction`.
+pub fn towards(direction: Direction) -> Connections {
+    match direction {
+        Direction::Up => UP,
+        Direction::Right => RIGHT,
+        Direction::Down => DOWN,
+        Direction::Left => LEFT,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineStyle {
+    Light,
+    Heavy,
+    Double,
+    Rounded,
+    Ascii,
+}
+
+impl LineStyle {
+    pub const ALL: [LineStyle; 5] = [
+        LineStyle::Light,
+        LineStyle::Heavy,
+        LineStyle::Double,
+        LineStyle::Rounded,
+        LineStyle::Ascii,
+    ];
+
+    /// The glyphs of this style, indexed by their [`Connections`].
+    fn table(&self) -> &'static [char; 16] {
+        match self {
+            LineStyle::Light => &LIGHT,
+            LineStyle::Heavy => &HEAVY,
+            LineStyle::Double => &DOUBLE,
+            LineStyle::Rounded => &ROUNDED,
+            LineStyle::Ascii => &ASCII,
+        }
+    }
+}
+
+impl fmt::Display for LineStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+impl FromStr for LineStyle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LineStyle::ALL
+            .into_iter()
+            .find(|style| style.to_string() == s)
+            .ok_or(())
+    }
+}
+
+/// The glyphs ending connectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrowhead {
+    Triangle,
+    Arrow,
+    Ascii,
+    /// Ends connectors with their line, joined to what they end on.
+    None,
+}
+
+impl Arrowhead {
+    pub const ALL: [Arrowhead; 4] = [
+        Arrowhead::Triangle,
+        Arrowhead::Arrow,
+        Arrowhead::Ascii,
+        Arrowhead::None,
+    ];
+
+    /// The arrowhead pointing towards `direction`, which is a plain line for [`Arrowhead::None`].
+    pub fn glyph(&self, direction: Direction) -> char {
+        let glyphs = match self {
+            Arrowhead::Triangle => ['▲', '▶', '▼', '◀'],
+            Arrowhead::Arrow => ['↑', '→', '↓', '←'],
+            Arrowhead::Ascii => ['^', '>', 'v', '<'],
+            Arrowhead::None => ['│', '─', '│', '─'],
+        };
+
+        glyphs[direction as usize]
+    }
+}
+
+impl fmt::Display for Arrowhead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+impl FromStr for Arrowhead {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Arrowhead::ALL
+            .into_iter()
+            .find(|arrowhead| arrowhead.to_string() == s)
+            .ok_or(())
+    }
+}
+
+#[rustfmt::skip]
+const LIGHT: [char; 16] = [
+    ' ', '╵', '╶', '└', '╷', '│', '┌', '├', '╴', '┘', '─', '┴', '┐', '┤', '┬', '┼',
+];
+#[rustfmt::skip]
+const HEAVY: [char; 16] = [
+    ' ', '╹', '╺', '┗', '╻', '┃', '┏', '┣', '╸', '┛', '━', '┻', '┓', '┫', '┳', '╋',
+];
+#[rustfmt::skip]
+const DOUBLE: [char; 16] = [
+    ' ', '║', '═', '╚', '║', '║', '╔', '╠', '═', '╝', '═', '╩', '╗', '╣', '╦', '╬',
+];
+#[rustfmt::skip]
+const ROUNDED: [char; 16] = [
+    ' ', '╵', '╶', '╰', '╷', '│', '╭', '├', '╴', '╯', '─', '┴', '╮', '┤', '┬', '┼',
+];
+#[rustfmt::skip]
+const ASCII: [char; 16] = [
+    ' ', '|', '-', '+', '|', '|', '+', '+', '-', '+', '-', '+', '+', '+', '+', '+',
+];
+
+lazy_static! {
+    /// Every Unicode box drawing glyph known to the editor, with its connections and style. ASCII
+    /// characters are left out on purpose, as they are just as likely to be plain text.
+    static ref GLYPHS: HashMap<char, (Connections, LineStyle)> = {
+        let mut map: HashMap<char, (Connections, LineStyle)> = HashMap::new();
+
+        for style in [LineStyle::Light, LineStyle::Heavy, LineStyle::Double, LineStyle::Rounded] {
+            for (connections, c) in style.table().iter().enumerate().skip(1) {
+                let connections = connections as Connections;
+
+                // Double lines have no half glyphs, so keep the full line for those.
+                match map.get(c) {
+                    Some((existing, _)) if existing.count_ones() >= connections.count_ones() => {}
+                    _ => {
+                        map.insert(*c, (connections, style));
+                    }
+                }
+            }
+        }
+
+        map
+    };
+}
+
+/// The glyph with the given connections in the given style, or a space if there are none.
+pub fn glyph(connections: Connections, style: LineStyle) -> char {
+    style.table()[(connections & 0b1111) as usize]
+}
+
+/// The connections and style of a box drawing glyph, if `c` is one.
+pub fn connections(c: char) -> Option<(Connections, LineStyle)> {
+    GLYPHS.get(&c).copied()
+}
+
+/// Redraws a box drawing glyph in another line style, leaving any other character untouched.
+pub fn restyle(c: char, style: LineStyle) -> char {
+    match connections(c) {
+        Some((connections, _)) => glyph(connections, style),
+        None => c,
+    }
+}
@@ -0,0 +1,151 @@
+//! Insertion and deletion of whole rows and columns of the grid, moving everything after them.
+
+use crate::{
+    glyphs::{self, Connections, LineStyle},
+    objects::{self, Endpoint, Objects},
+    text_area::{BoundingBox, Coordinates, TextArea},
+};
+
+/// A change of the rows or columns of the grid, at the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridEdit {
+    /// Inserts a row above the cursor, moving it down.
+    InsertRow,
+    /// Inserts a column left of the cursor, moving it right.
+    InsertColumn,
+    DeleteRow,
+    DeleteColumn,
+}
+
+impl GridEdit {
+    /// Where what is at `position` goes once the row or column at `at` is inserted or deleted.
+    /// What is on a deleted row or column stays where it is.
+    pub fn moved(&self, at: Coordinates, position: Coordinates) -> Coordinates {
+        let Coordinates { x, y } = position;
+
+        match self {
+            GridEdit::InsertRow if y >= at.y => (x, y + 1),
+            GridEdit::InsertColumn if x >= at.x => (x + 1, y),
+            GridEdit::DeleteRow if y > at.y => (x, y - 1),
+            GridEdit::DeleteColumn if x > at.x => (x - 1, y),
+            _ => (x, y),
+        }
+        .into()
+    }
+
+    /// The box `bounds` becomes, stretched by an inserted line crossing it or shrunk by a deleted
+    /// one, but never smaller than a corner on each side.
+    fn bounds(&self, at: Coordinates, bounds: BoundingBox) -> BoundingBox {
+        let top_left = self.moved(at, bounds.top_left);
+        let bottom_right = self.moved(at, bounds.bottom_right());
+
+        BoundingBox::new(
+            top_left,
+            ((bottom_right.x - top_left.x + 1) as usize).max(2),
+            ((bottom_right.y - top_left.y + 1) as usize).max(2),
+        )
+    }
+
+    /// Moves the objects along with the cells under them.
+    fn objects(&self, at: Coordinates, objects: &mut Objects) {
+        for shape in objects.shapes.values_mut() {
+            shape.bounds = self.bounds(at, shape.bounds);
+        }
+
+        for link in &mut objects.links {
+            for endpoint in [&mut link.from, &mut link.to] {
+                if let Endpoint::Cell(position) = endpoint {
+                    *position = self.moved(at, *position);
+                }
+            }
+        }
+
+        for table in objects.tables.values_mut() {
+            table.top_left = self.moved(at, table.top_left);
+        }
+
+        for sequence in objects.sequences.values_mut() {
+            sequence.origin = self.moved(at, sequence.origin);
+        }
+    }
+}
+
+/// The straight line to extend `c` with, if it has a line going towards `connection`.
+fn extension(c: Option<char>, connection: Connections, straight: Connections) -> Option<char> {
+    let c = c?;
+
+    match glyphs::connections(c) {
+        Some((connections, style)) if connections & connection != 0 => {
+            Some(glyphs::glyph(straight, style))
+        }
+        Some(_) => None,
+        // ASCII lines, which are only joined to ASCII lines
+        None => {
+            let ascii = glyphs::glyph(straight, LineStyle::Ascii);
+            (c == ascii || c == '+').then_some(ascii)
+        }
+    }
+}
+
+/// Inserts or deletes the row or column at `at`, moving what comes after it along with the
+/// objects, as a single undo step. The lines going across an inserted row or column
+/// are extended through it, so that boxes stretch instead of breaking.
+pub fn edit(area: &mut TextArea, at: Coordinates, edit: GridEdit) {
+    area.begin_undo_group();
+
+    // The objects are lifted off the grid and drawn again once moved
+    let mut objects = area.objects().clone();
+    objects::update(area, Objects::default());
+
+    let on_line = |position: &Coordinates| match edit {
+        GridEdit::InsertRow | GridEdit::DeleteRow => position.y == at.y,
+        GridEdit::InsertColumn | GridEdit::DeleteColumn => position.x == at.x,
+    };
+
+    if matches!(edit, GridEdit::DeleteRow | GridEdit::DeleteColumn) {
+        let deleted = area
+            .graphemes()
+            .map(|(position, _)| position)
+            .filter(on_line)
+            .collect::<Vec<_>>();
+
+        for position in deleted {
+            area.erase_at(position);
+        }
+    }
+
+    match edit {
+        GridEdit::InsertRow => area.shift_rows(at.y, 1),
+        GridEdit::InsertColumn => area.shift_columns(at.x, 1),
+        GridEdit::DeleteRow => area.shift_rows(at.y + 1, -1),
+        GridEdit::DeleteColumn => area.shift_columns(at.x + 1, -1),
+    }
+
+    // The lines ending right before an inserted row or column, and those right after it
+    let across = match edit {
+        GridEdit::InsertRow => Some((Coordinates::from((0, 1)), glyphs::DOWN, glyphs::UP)),
+        GridEdit::InsertColumn => Some(((1, 0).into(), glyphs::RIGHT, glyphs::LEFT)),
+        _ => None,
+    };
+
+    if let Some((step, forwards, backwards)) = across {
+        let straight = forwards | backwards;
+        let extended = area
+            .graphemes()
+            .map(|(position, _)| position + step)
+            .filter(on_line)
+            .filter_map(|position| {
+                let c = extension(area.char_at(position - step), forwards, straight)?;
+                extension(area.char_at(position + step), backwards, straight).map(|_| (position, c))
+            })
+            .collect::<Vec<_>>();
+
+        for (position, c) in extended {
+            area.write_at(position, c);
+        }
+    }
+
+    edit.objects(at, &mut objects);
+    objects::update(area, objects);
+    area.end_undo_group();
+}
@@ -2,11 +2,13 @@ use lazy_static::lazy_static;
 use winit::keyboard::{Key, ModifiersState, NamedKey};
 
 use crate::{
-    keymap::{BoxMode, ColorMode, ExtraMode, InputMode, TextMode},
+    draw::RectangleFill,
+    keymap::{BoxMode, ColorMode, ExtraMode, InputMode, NamePrompt, TextMode},
     pragmata_pro_input::Segment,
+    text_area::{Coordinates, Direction},
 };
 
-use super::{Action, InputMap, InputModeIdentifier, KeyMap, CTRL, NONE};
+use super::{Action, InputMap, InputModeIdentifier, KeyMap, ALT, CTRL, NONE, SHIFT};
 
 lazy_static! {
     static ref BOX: InputMap = {
@@ -32,18 +34,50 @@ lazy_static! {
             ((NONE, Key::Named(NamedKey::ArrowRight)), vec![Action::CursorRight]),
             ((NONE, Key::Named(NamedKey::ArrowUp)), vec![Action::CursorUp]),
             ((NONE, Key::Named(NamedKey::ArrowDown)), vec![Action::CursorDown]),
+            ((CTRL, Key::Named(NamedKey::Home)), vec![Action::GoTo(Coordinates::from((0, 0)))]),
+            ((CTRL, Key::Character("g".into())), vec![Action::OpenCommandLine("goto ".to_string())]),
+            ((CTRL, Key::Character("f".into())), vec![Action::OpenCommandLine("find ".to_string())]),
+            ((CTRL, Key::Character("k".into())), vec![Action::OpenPalette(String::new())]),
+            ((NONE, Key::Named(NamedKey::F3)), vec![Action::FindNext]),
+            ((SHIFT, Key::Named(NamedKey::F3)), vec![Action::FindPrevious]),
+            ((NONE, Key::Named(NamedKey::F8)), vec![Action::NextIssue]),
+            ((SHIFT, Key::Named(NamedKey::F8)), vec![Action::PreviousIssue]),
             ((NONE, Key::Named(NamedKey::Delete)), vec![Action::DeleteAtCursor]),
             ((NONE, Key::Named(NamedKey::Backspace)), vec![Action::CursorLeft, Action::DeleteAtCursor]),
+            ((NONE, Key::Named(NamedKey::Enter)), vec![Action::Rectangle(RectangleFill::Outline)]),
+            ((SHIFT, Key::Named(NamedKey::Enter)), vec![Action::Rectangle(RectangleFill::Clear)]),
+            ((CTRL, Key::Named(NamedKey::Enter)), vec![Action::Connector]),
+            ((ALT, Key::Named(NamedKey::Enter)), vec![Action::AddShape]),
+            ((ALT, Key::Character("t".into())), vec![Action::AddTextBox]),
+            ((ALT, Key::Named(NamedKey::Delete)), vec![Action::DeleteShape]),
+            ((ALT, Key::Named(NamedKey::ArrowLeft)), vec![Action::MoveShape(Direction::Left)]),
+            ((ALT, Key::Named(NamedKey::ArrowRight)), vec![Action::MoveShape(Direction::Right)]),
+            ((ALT, Key::Named(NamedKey::ArrowUp)), vec![Action::MoveShape(Direction::Up)]),
+            ((ALT, Key::Named(NamedKey::ArrowDown)), vec![Action::MoveShape(Direction::Down)]),
+            ((ALT | SHIFT, Key::Named(NamedKey::ArrowLeft)), vec![Action::ResizeShape(Direction::Right, false)]),
+            ((ALT | SHIFT, Key::Named(NamedKey::ArrowRight)), vec![Action::ResizeShape(Direction::Right, true)]),
+            ((ALT | SHIFT, Key::Named(NamedKey::ArrowUp)), vec![Action::ResizeShape(Direction::Down, false)]),
+            ((ALT | SHIFT, Key::Named(NamedKey::ArrowDown)), vec![Action::ResizeShape(Direction::Down, true)]),
+            ((NONE, Key::Named(NamedKey::Tab)), vec![Action::NextRegion]),
+            ((SHIFT, Key::Named(NamedKey::Tab)), vec![Action::PreviousRegion]),
+            ((NONE, Key::Character("m".into())), vec![Action::AwaitName(NamePrompt::SetMark)]),
+            ((NONE, Key::Character("'".into())), vec![Action::AwaitName(NamePrompt::JumpToMark)]),
             ((NONE, Key::Character(")".into())), vec![Action::ReduceFontSize]),
             ((NONE, Key::Character("=".into())), vec![Action::IncreaseFontSize]),
+            ((NONE, Key::Character(":".into())), vec![Action::OpenCommandLine(String::new())]),
             ((CTRL, Key::Character("z".into())), vec![Action::Undo]),
             ((CTRL, Key::Character("y".into())), vec![Action::Redo]),
+            ((SHIFT, Key::Character(".".into())), vec![Action::Repeat]),
+            ((CTRL, Key::Character("t".into())), vec![Action::NextTheme]),
+            ((CTRL, Key::Character("r".into())), vec![Action::ToggleRecording]),
+            ((CTRL, Key::Character("p".into())), vec![Action::AwaitName(NamePrompt::PlayMacro)]),
 
             // Transition
             ((CTRL, Key::Character("&".into())), vec![Action::Transition(InputMode::Box(BoxMode))]),
             ((CTRL, Key::Character("é".into())), vec![Action::Transition(InputMode::Text(TextMode))]),
             ((CTRL, Key::Character("\"".into())), vec![Action::Transition(InputMode::Color(ColorMode))]),
             ((CTRL, Key::Character("'".into())), vec![Action::Transition(InputMode::Extra(ExtraMode { buffer: Vec::new()}))]),
+            ((CTRL, Key::Character("(".into())), vec![Action::OpenCommandLine(String::new())]),
         ];
 
         m.into_iter().collect()
@@ -57,16 +91,32 @@ lazy_static! {
             ((NONE, Key::Named(NamedKey::ArrowRight)), vec![Action::CursorRight]),
             ((NONE, Key::Named(NamedKey::ArrowUp)), vec![Action::CursorUp]),
             ((NONE, Key::Named(NamedKey::ArrowDown)), vec![Action::CursorDown]),
+            ((CTRL, Key::Named(NamedKey::Home)), vec![Action::GoTo(Coordinates::from((0, 0)))]),
+            ((CTRL, Key::Character("g".into())), vec![Action::OpenCommandLine("goto ".to_string())]),
+            ((CTRL, Key::Character("f".into())), vec![Action::OpenCommandLine("find ".to_string())]),
+            ((CTRL, Key::Character("k".into())), vec![Action::OpenPalette(String::new())]),
+            ((NONE, Key::Named(NamedKey::F3)), vec![Action::FindNext]),
+            ((SHIFT, Key::Named(NamedKey::F3)), vec![Action::FindPrevious]),
+            ((NONE, Key::Named(NamedKey::F8)), vec![Action::NextIssue]),
+            ((SHIFT, Key::Named(NamedKey::F8)), vec![Action::PreviousIssue]),
             ((NONE, Key::Named(NamedKey::Delete)), vec![Action::DeleteAtCursor]),
-            ((NONE, Key::Named(NamedKey::Backspace)), vec![Action::CursorLeft, Action::DeleteAtCursor]),
+            ((NONE, Key::Named(NamedKey::Backspace)), vec![Action::Backspace]),
+            ((NONE, Key::Named(NamedKey::Enter)), vec![Action::NewLine]),
+            ((NONE, Key::Named(NamedKey::Tab)), vec![Action::Tab]),
+            ((NONE, Key::Named(NamedKey::Insert)), vec![Action::ToggleInsert]),
             ((CTRL, Key::Character("z".into())), vec![Action::Undo]),
             ((CTRL, Key::Character("y".into())), vec![Action::Redo]),
+            ((ALT, Key::Character(";".into())), vec![Action::Repeat]),
+            ((CTRL, Key::Character("t".into())), vec![Action::NextTheme]),
+            ((CTRL, Key::Character("r".into())), vec![Action::ToggleRecording]),
+            ((CTRL, Key::Character("p".into())), vec![Action::AwaitName(NamePrompt::PlayMacro)]),
 
             // Transition
             ((CTRL, Key::Character("&".into())), vec![Action::Transition(InputMode::Box(BoxMode))]),
             ((CTRL, Key::Character("é".into())), vec![Action::Transition(InputMode::Text(TextMode))]),
             ((CTRL, Key::Character("\"".into())), vec![Action::Transition(InputMode::Color(ColorMode))]),
             ((CTRL, Key::Character("'".into())), vec![Action::Transition(InputMode::Extra(ExtraMode { buffer: Vec::new()}))]),
+            ((CTRL, Key::Character("(".into())), vec![Action::OpenCommandLine(String::new())]),
         ];
 
         m.into_iter().collect()
@@ -80,18 +130,36 @@ lazy_static! {
             ((NONE, Key::Named(NamedKey::ArrowRight)), vec![Action::CursorRight]),
             ((NONE, Key::Named(NamedKey::ArrowUp)), vec![Action::CursorUp]),
             ((NONE, Key::Named(NamedKey::ArrowDown)), vec![Action::CursorDown]),
+            ((CTRL, Key::Named(NamedKey::Home)), vec![Action::GoTo(Coordinates::from((0, 0)))]),
+            ((CTRL, Key::Character("g".into())), vec![Action::OpenCommandLine("goto ".to_string())]),
+            ((CTRL, Key::Character("f".into())), vec![Action::OpenCommandLine("find ".to_string())]),
+            ((CTRL, Key::Character("k".into())), vec![Action::OpenPalette(String::new())]),
+            ((NONE, Key::Named(NamedKey::F3)), vec![Action::FindNext]),
+            ((SHIFT, Key::Named(NamedKey::F3)), vec![Action::FindPrevious]),
+            ((NONE, Key::Named(NamedKey::F8)), vec![Action::NextIssue]),
+            ((SHIFT, Key::Named(NamedKey::F8)), vec![Action::PreviousIssue]),
             ((NONE, Key::Named(NamedKey::Delete)), vec![Action::DeleteAtCursor]),
             ((NONE, Key::Named(NamedKey::Backspace)), vec![Action::CursorLeft, Action::DeleteAtCursor]),
+            ((NONE, Key::Named(NamedKey::Tab)), vec![Action::NextRegion]),
+            ((SHIFT, Key::Named(NamedKey::Tab)), vec![Action::PreviousRegion]),
+            ((NONE, Key::Character("m".into())), vec![Action::AwaitName(NamePrompt::SetMark)]),
+            ((NONE, Key::Character("'".into())), vec![Action::AwaitName(NamePrompt::JumpToMark)]),
             ((NONE, Key::Character(")".into())), vec![Action::ReduceFontSize]),
             ((NONE, Key::Character("=".into())), vec![Action::IncreaseFontSize]),
+            ((NONE, Key::Character(":".into())), vec![Action::OpenCommandLine(String::new())]),
             ((CTRL, Key::Character("z".into())), vec![Action::Undo]),
             ((CTRL, Key::Character("y".into())), vec![Action::Redo]),
+            ((SHIFT, Key::Character(".".into())), vec![Action::Repeat]),
+            ((CTRL, Key::Character("t".into())), vec![Action::NextTheme]),
+            ((CTRL, Key::Character("r".into())), vec![Action::ToggleRecording]),
+            ((CTRL, Key::Character("p".into())), vec![Action::AwaitName(NamePrompt::PlayMacro)]),
 
             // Transition
             ((CTRL, Key::Character("&".into())), vec![Action::Transition(InputMode::Box(BoxMode))]),
             ((CTRL, Key::Character("é".into())), vec![Action::Transition(InputMode::Text(TextMode))]),
             ((CTRL, Key::Character("\"".into())), vec![Action::Transition(InputMode::Color(ColorMode))]),
             ((CTRL, Key::Character("'".into())), vec![Action::Transition(InputMode::Extra(ExtraMode { buffer: Vec::new()}))]),
+            ((CTRL, Key::Character("(".into())), vec![Action::OpenCommandLine(String::new())]),
         ];
 
         m.into_iter().collect()
@@ -105,17 +173,29 @@ lazy_static! {
             ((NONE, Key::Named(NamedKey::ArrowRight)), vec![Action::CursorRight]),
             ((NONE, Key::Named(NamedKey::ArrowUp)), vec![Action::CursorUp]),
             ((NONE, Key::Named(NamedKey::ArrowDown)), vec![Action::CursorDown]),
+            ((CTRL, Key::Named(NamedKey::Home)), vec![Action::GoTo(Coordinates::from((0, 0)))]),
+            ((CTRL, Key::Character("g".into())), vec![Action::OpenCommandLine("goto ".to_string())]),
+            ((CTRL, Key::Character("f".into())), vec![Action::OpenCommandLine("find ".to_string())]),
+            ((CTRL, Key::Character("k".into())), vec![Action::OpenPalette(String::new())]),
+            ((NONE, Key::Named(NamedKey::F3)), vec![Action::FindNext]),
+            ((SHIFT, Key::Named(NamedKey::F3)), vec![Action::FindPrevious]),
+            ((NONE, Key::Named(NamedKey::F8)), vec![Action::NextIssue]),
+            ((SHIFT, Key::Named(NamedKey::F8)), vec![Action::PreviousIssue]),
             ((NONE, Key::Named(NamedKey::Delete)), vec![Action::DeleteAtCursor]),
             ((NONE, Key::Character(")".into())), vec![Action::ReduceFontSize]),
             ((NONE, Key::Character("=".into())), vec![Action::IncreaseFontSize]),
             ((CTRL, Key::Character("z".into())), vec![Action::Undo]),
             ((CTRL, Key::Character("y".into())), vec![Action::Redo]),
+            ((CTRL, Key::Character("t".into())), vec![Action::NextTheme]),
+            ((CTRL, Key::Character("r".into())), vec![Action::ToggleRecording]),
+            ((CTRL, Key::Character("p".into())), vec![Action::AwaitName(NamePrompt::PlayMacro)]),
 
             // Transition
             ((CTRL, Key::Character("&".into())), vec![Action::Transition(InputMode::Box(BoxMode))]),
             ((CTRL, Key::Character("é".into())), vec![Action::Transition(InputMode::Text(TextMode))]),
             ((CTRL, Key::Character("\"".into())), vec![Action::Transition(InputMode::Color(ColorMode))]),
             ((CTRL, Key::Character("'".into())), vec![Action::Transition(InputMode::Extra(ExtraMode { buffer: Vec::new()}))]),
+            ((CTRL, Key::Character("(".into())), vec![Action::OpenCommandLine(String::new())]),
         ];
 
         m.into_iter().collect()
@@ -136,6 +216,8 @@ impl KeyMap for Azerty {
             InputModeIdentifier::Text => &TEXT,
             InputModeIdentifier::Color => &COLOR,
             InputModeIdentifier::Extra => &EXTRA,
+            // Every key edits the command line or the search of the palette
+            InputModeIdentifier::Command | InputModeIdentifier::Palette => return None,
         };
 
         map.get(&(modifiers, key)).map(|v| v.as_slice())
@@ -1,6 +1,18 @@
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, path::PathBuf};
 
-use crate::pragmata_pro_input::{code_to_codepoint, Segment};
+use crate::{
+    config::TabStops,
+    draw::RectangleFill,
+    export::ExportFormat,
+    glyphs::{Arrowhead, LineStyle},
+    grid::GridEdit,
+    objects::Align,
+    palette::Palette,
+    pragmata_pro_input::{code_to_codepoint, Segment},
+    table::TableEdit,
+    text_area::{Coordinates, Direction},
+    transform::Transform,
+};
 use winit::keyboard::{Key, ModifiersState};
 
 mod azerty;
@@ -45,18 +57,234 @@ impl ExtraMode {
     }
 }
 
+#[derive(Clone)]
+pub struct CommandMode {
+    pub buffer: String,
+    /// The mode to go back to once the command has been run or cancelled.
+    pub previous: Box<InputMode>,
+    /// The entry of the command history currently shown, if any.
+    pub history_index: Option<usize>,
+    /// The candidates of the ongoing tab completion, and the one currently shown.
+    pub completions: Option<(Vec<String>, usize)>,
+}
+
+impl CommandMode {
+    pub fn new(previous: InputMode) -> Self {
+        Self {
+            buffer: String::new(),
+            previous: Box::new(previous),
+            history_index: None,
+            completions: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PaletteMode {
+    pub palette: Palette,
+    /// The mode to go back to once a character has been picked or the palette closed.
+    pub previous: Box<InputMode>,
+}
+
+/// What to do with the mark or macro register named by the next key press.
+#[derive(Clone, Copy)]
+pub enum NamePrompt {
+    SetMark,
+    JumpToMark,
+    RecordMacro,
+    PlayMacro,
+    /// Binds the character to the macro register, to be drawn by playing it.
+    BindChar(char),
+}
+
+#[derive(Clone)]
 pub enum Action {
     CursorLeft,
     CursorRight,
     CursorUp,
     CursorDown,
+    MoveCursor(Direction, usize),
     DrawCharAtCursor(char),
+    /// Draws a character as it is, box drawing glyphs keeping their own style.
+    PutChar(char),
     DeleteAtCursor,
+    /// Types a character in Text mode, moving the cursor after it.
+    TypeChar(char),
+    /// Moves to the next row, at the column where typing began.
+    NewLine,
+    Tab,
+    Backspace,
+    /// Switches Text mode between overwriting and inserting.
+    ToggleInsert,
+    /// Inserts or deletes the row or column of the cursor, moving everything after it.
+    EditGrid(GridEdit),
+    /// Sets the anchor of a rectangle at the cursor, or draws the rectangle between the anchor and
+    /// the cursor if there is one.
+    Rectangle(RectangleFill),
+    /// Covers the blank cells around the cursor, or those holding the same character as it, with
+    /// a character.
+    Fill(char),
+    /// Erases everything inside the box drawing lines around the cursor.
+    ClearRegion,
+    /// Sets the anchor of a connector at the cursor, or draws a connector routed from the anchor
+    /// to the cursor if there is one. The connector is bound to the shapes its ends are on.
+    Connector,
+    /// Sets the anchor of a shape at the cursor, or adds a shape between the anchor and the cursor
+    /// to the object layer if there is one.
+    AddShape,
+    /// Moves the shape under the cursor, along with the cursor.
+    MoveShape(Direction),
+    /// Moves the side of the shape under the cursor facing the direction, outwards if set.
+    ResizeShape(Direction, bool),
+    SetLabel(String),
+    DeleteShape,
+    /// Adds a text box at the cursor and starts typing into it in Text mode.
+    AddTextBox,
+    SetTextWidth(usize),
+    SetTextAlign(Align),
+    SetTextPadding(usize),
+    /// Adds an empty table of the given rows and columns at the cursor.
+    AddTable(usize, usize),
+    /// Adds a table at the cursor holding the comma or tab separated values of a file.
+    ImportTable(PathBuf),
+    /// Inserts or deletes a row or column of the table under the cursor.
+    EditTable(TableEdit),
+    /// Draws the tree read from a file of indented text or paths at the cursor.
+    InsertTree(PathBuf),
+    /// Writes the tree whose root is at the cursor to a file, as indented text.
+    ExtractTree(PathBuf),
+    /// Draws the sequence diagram read from a file at the cursor, or regenerates the one under the
+    /// cursor from it.
+    InsertSequence(PathBuf),
+    /// Writes the source of the sequence diagram under the cursor to a file.
+    ExtractSequence(PathBuf),
+    /// Sets the length of the horizontal lines leading to the children of trees.
+    SetTreeBranch(usize),
+    SetArrowhead(Arrowhead),
+    SetTabStops(TabStops),
     ReduceFontSize,
     IncreaseFontSize,
     Undo,
     Redo,
     Transition(InputMode),
+    NextTheme,
+    /// Opens the command line with the given text already typed in.
+    OpenCommandLine(String),
+    /// Opens the character palette, searching for the given text.
+    OpenPalette(String),
+    GoTo(Coordinates),
+    AwaitName(NamePrompt),
+    SetMark(char),
+    JumpToMark(char),
+    NextRegion,
+    PreviousRegion,
+    /// Runs the last sequence of actions that changed the drawing again.
+    Repeat,
+    /// Starts recording a macro, asking for its register, or stops the ongoing recording.
+    ToggleRecording,
+    RecordMacro(char),
+    PlayMacro(char),
+    /// Makes the macro register draw the character at the cursor.
+    BindChar(char, char),
+    SaveMacros,
+    SetLineStyle(LineStyle),
+    /// Converts the lines in the rectangle between the anchor and the cursor, or in the whole
+    /// drawing, between ASCII art and box drawing in the given style.
+    Convert(LineStyle),
+    /// Looks for text in the drawing and moves to the first match after the cursor, or stops
+    /// searching if empty.
+    Find(String),
+    FindNext,
+    FindPrevious,
+    /// Replaces every match of the search in the rectangle between the anchor and the cursor, or
+    /// in the whole drawing.
+    ReplaceAll(String),
+    /// Sets whether searches look along columns as well as rows.
+    SetSearchColumns(bool),
+    /// Moves to the next glyph whose lines don't match its neighbours, or the previous one.
+    NextIssue,
+    PreviousIssue,
+    /// Joins or trims the glyphs whose lines don't match their neighbours.
+    FixIssues,
+    /// Flips or turns the rectangle between the anchor and the cursor, moving runs of text
+    /// without mirroring them if set.
+    Transform(Transform, bool),
+    SetFontSize(f32),
+    SetGuideSpacing(usize),
+    SetTheme(String),
+    Save(Option<PathBuf>),
+    /// Saves, then quits if the drawing was written.
+    SaveAndQuit(Option<PathBuf>),
+    /// Opens a file, even if the drawing has unsaved changes when set.
+    Open(PathBuf, bool),
+    /// Replaces the drawing with a new one laid out from a Graphviz DOT file, even if it has
+    /// unsaved changes when set.
+    ImportDot(PathBuf, bool),
+    Export(ExportFormat, PathBuf),
+    Source(PathBuf),
+    /// Quits, even if the drawing has unsaved changes when set.
+    Quit(bool),
+}
+
+impl Action {
+    /// Whether the action changes the content of the drawing.
+    pub fn is_change(&self) -> bool {
+        matches!(
+            self,
+            Action::DrawCharAtCursor(_)
+                | Action::PutChar(_)
+                | Action::DeleteAtCursor
+                | Action::TypeChar(_)
+                | Action::NewLine
+                | Action::Tab
+                | Action::Backspace
+                | Action::Rectangle(_)
+                | Action::Connector
+                | Action::AddShape
+                | Action::MoveShape(_)
+                | Action::ResizeShape(..)
+                | Action::SetLabel(_)
+                | Action::DeleteShape
+                | Action::AddTextBox
+                | Action::AddTable(..)
+                | Action::EditTable(_)
+                | Action::InsertTree(_)
+                | Action::InsertSequence(_)
+                | Action::Convert(_)
+                | Action::Transform(..)
+                | Action::Fill(_)
+                | Action::ClearRegion
+                | Action::EditGrid(_)
+                | Action::ReplaceAll(_)
+                | Action::FixIssues
+        )
+    }
+
+    /// Whether the action is part of typing text, a run of those being a single undo step.
+    pub fn is_typing(&self) -> bool {
+        matches!(
+            self,
+            Action::TypeChar(_) | Action::NewLine | Action::Tab | Action::Backspace
+        )
+    }
+
+    /// Whether the action goes into the macro being recorded. Actions that only exist to run or
+    /// prompt for other actions are left out, as what they end up running is recorded instead.
+    pub fn is_recorded(&self) -> bool {
+        !matches!(
+            self,
+            Action::OpenCommandLine(_)
+                | Action::OpenPalette(_)
+                | Action::AwaitName(_)
+                | Action::Repeat
+                | Action::ToggleRecording
+                | Action::RecordMacro(_)
+                | Action::PlayMacro(_)
+                | Action::SaveMacros
+                | Action::Source(_)
+                | Action::Transition(InputMode::Command(_) | InputMode::Palette(_))
+        )
+    }
 }
 
 pub enum InputModeIdentifier {
@@ -64,6 +292,8 @@ pub enum InputModeIdentifier {
     Text,
     Color,
     Extra,
+    Command,
+    Palette,
 }
 
 #[derive(Clone)]
@@ -72,6 +302,8 @@ pub enum InputMode {
     Text(TextMode),
     Color(ColorMode),
     Extra(ExtraMode),
+    Command(CommandMode),
+    Palette(PaletteMode),
 }
 
 impl fmt::Display for InputMode {
@@ -81,6 +313,8 @@ impl fmt::Display for InputMode {
             InputMode::Text(_) => write!(f, "Text"),
             InputMode::Color(_) => write!(f, "Color"),
             InputMode::Extra(_) => write!(f, "Extra"),
+            InputMode::Command(_) => write!(f, "Command"),
+            InputMode::Palette(_) => write!(f, "Palette"),
         }
     }
 }
@@ -92,6 +326,8 @@ impl InputMode {
             InputMode::Text(_) => InputModeIdentifier::Text,
             InputMode::Color(_) => InputModeIdentifier::Color,
             InputMode::Extra(_) => InputModeIdentifier::Extra,
+            InputMode::Command(_) => InputModeIdentifier::Command,
+            InputMode::Palette(_) => InputModeIdentifier::Palette,
         }
     }
 }
@@ -0,0 +1,211 @@
+//! Checks of box drawing glyphs against their neighbours, finding junctions with an arm going
+//! nowhere and lines running into the side of others.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    convert,
+    glyphs::{self, Connections},
+    text_area::{Coordinates, Direction, TextArea},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// A junction or corner has an arm going towards something that isn't a line.
+    Dangling,
+    /// A line runs into the side of a glyph that has no line going back towards it.
+    SideOn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Issue {
+    pub position: Coordinates,
+    pub glyph: char,
+    /// Where the faulty line goes from the glyph.
+    pub direction: Direction,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problem {
+            Problem::Dangling => write!(
+                f,
+                "`{}` has a line going {} to nothing",
+                self.glyph, self.direction
+            ),
+            Problem::SideOn => write!(
+                f,
+                "`{}` has a line going {} into the side of another",
+                self.glyph, self.direction
+            ),
+        }
+    }
+}
+
+/// Whether a glyph with `connections` is only a piece of a straight line, or its end, which can
+/// stop anywhere.
+fn is_straight(connections: Connections) -> bool {
+    connections.count_ones() == 1
+        || connections == glyphs::UP | glyphs::DOWN
+        || connections == glyphs::LEFT | glyphs::RIGHT
+}
+
+/// Every issue of the box drawing glyphs of `area`, from top to bottom and left to right.
+pub fn check(area: &TextArea) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (position, grapheme) in area.graphemes() {
+        let Some(glyph) = grapheme.chars().next() else {
+            continue;
+        };
+        let Some((connections, _)) = glyphs::connections(glyph) else {
+            continue;
+        };
+
+        for direction in Direction::ALL {
+            if connections & glyphs::towards(direction) == 0 {
+                continue;
+            }
+
+            let neighbour = area.char_at(position + direction.vector());
+            let back = glyphs::towards(direction.opposite());
+
+            let problem = match neighbour.and_then(glyphs::connections) {
+                Some((connections, _)) if connections & back != 0 => continue,
+                Some(_) => Problem::SideOn,
+                None if neighbour.and_then(convert::arrowhead).is_some()
+                    || is_straight(connections) =>
+                {
+                    continue
+                }
+                None => Problem::Dangling,
+            };
+
+            issues.push(Issue {
+                position,
+                glyph,
+                direction,
+                problem,
+            });
+        }
+    }
+
+    issues.sort_by_key(|i| (i.position.y, i.position.x, i.direction as usize));
+    issues
+}
+
+/// The index of the first issue after `position`, or before it going backwards, wrapping around
+/// the ends.
+pub fn next(issues: &[Issue], position: Coordinates, forward: bool) -> Option<usize> {
+    let key = |p: Coordinates| (p.y, p.x);
+    let position = key(position);
+
+    if forward {
+        issues
+            .iter()
+            .position(|i| key(i.position) > position)
+            .or((!issues.is_empty()).then_some(0))
+    } else {
+        // The first issue of the glyph before the cursor, as a glyph can have several
+        let before = issues.iter().rposition(|i| key(i.position) < position);
+        let last = before.or(issues.len().checked_sub(1))?;
+        issues
+            .iter()
+            .position(|i| i.position == issues[last].position)
+    }
+}
+
+/// Fixes every issue, as a single undo step: glyphs run into side-on are joined to the line, and
+/// dangling arms are removed. Joining can leave the other arms of a glyph dangling, so this goes
+/// on until no more issues can be fixed. Returns how many issues there were to begin with.
+pub fn fix(area: &mut TextArea) -> usize {
+    let mut issues = check(area);
+    let count = issues.len();
+
+    area.begin_undo_group();
+
+    while !issues.is_empty() {
+        // Several issues can be about the same glyph, so each is fixed on top of the others
+        let mut fixed = HashMap::new();
+
+        for issue in &issues {
+            let (position, add, remove) = match issue.problem {
+                Problem::Dangling => (issue.position, 0, glyphs::towards(issue.direction)),
+                Problem::SideOn => (
+                    issue.position + issue.direction.vector(),
+                    glyphs::towards(issue.direction.opposite()),
+                    0,
+                ),
+            };
+
+            let current = fixed.get(&position).copied().or(area.char_at(position));
+
+            if let Some((connections, style)) = current.and_then(glyphs::connections) {
+                fixed.insert(
+                    position,
+                    glyphs::glyph((connections | add) & !remove, style),
+                );
+            }
+        }
+
+        for (position, glyph) in fixed {
+            area.write_at(position, glyph);
+        }
+
+        // Double lines have no half glyphs, so some can't be fixed
+        let left = check(area);
+        if left == issues {
+            break;
+        }
+        issues = left;
+    }
+
+    area.end_undo_group();
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(rows: &[&str]) -> TextArea {
+        let mut area = TextArea::new(0, 0);
+
+        for (y, row) in rows.iter().enumerate() {
+            area.write_str_at((0, y as isize).into(), row);
+        }
+
+        area
+    }
+
+    #[test]
+    fn check_glyphs() {
+        use Direction::*;
+        use Problem::*;
+
+        for (rows, issues) in [
+            (&["┌─┐", "└─┘"][..], &[][..]),
+            (&["a─b", "│"], &[]),
+            (&["─┬─", " ▼"], &[]),
+            (&["─┐"], &[(1, 0, Down, Dangling)]),
+            (&["──│"], &[(1, 0, Right, SideOn)]),
+            (
+                &["├x", "━┓"],
+                &[
+                    (0, 0, Up, Dangling),
+                    (0, 0, Right, Dangling),
+                    (0, 0, Down, SideOn),
+                    (1, 1, Down, Dangling),
+                ],
+            ),
+        ] {
+            let found = check(&area(rows))
+                .into_iter()
+                .map(|i| (i.position.x, i.position.y, i.direction, i.problem))
+                .collect::<Vec<_>>();
+
+            assert_eq!(found, issues, "{rows:?}");
+        }
+    }
+}
@@ -1,33 +1,87 @@
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{BTreeMap, HashSet, VecDeque},
     fmt,
     num::NonZeroU32,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
     time::{Duration, Instant},
 };
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{Config, TabStops};
+use document::Document;
 use fontdue::{Font, FontSettings};
-use keymap::{Action, Azerty, BoxMode, Bépo, ExtraMode, InputMode, KeyMap, Qwerty};
-use softbuffer::{Context, Surface};
+use glyphs::{Arrowhead, LineStyle};
+use keymap::{
+    Action, Azerty, BoxMode, Bépo, CommandMode, ExtraMode, InputMode, KeyMap, NamePrompt,
+    PaletteMode, Qwerty, TextMode,
+};
+use unicode_segmentation::UnicodeSegmentation;
 use winit::{
-    event::{ElementState, Event, KeyEvent, StartCause, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, Ime, KeyEvent, StartCause, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     keyboard::{Key, ModifiersState, NamedKey},
     window::{Window, WindowBuilder},
 };
 
 mod canvas;
-use canvas::Canvas;
+use canvas::{Canvas, Overlay};
+
+mod command;
+
+mod config;
+
+mod convert;
+
+mod document;
+
+mod dot;
+
+mod draw;
+
+mod export;
+
+mod glyphs;
+
+mod grid;
 
 mod keymap;
 
+mod lint;
+
+mod objects;
+use objects::{Endpoint, Link, Objects, Shape, TextLayout, TextTarget};
+
+mod palette;
+use palette::{Palette, Row};
+
 mod pragmata_pro_input;
-use text_area::Direction;
+
+mod route;
+
+mod search;
+
+mod sequence;
+use sequence::Sequence;
+
+mod table;
+use table::{Table, TableEdit};
+
+use text_area::{BoundingBox, Coordinates, Direction, TextArea};
 
 mod text_area;
 
+mod theme;
+use theme::Theme;
+
+mod transform;
+
+mod tree;
+
+/// The columns taken by each character of the palette, leaving room for wide ones.
+const PALETTE_CELL: usize = 2;
+
 struct App {
     window: Rc<Window>,
     key_map: Box<dyn KeyMap>,
@@ -36,35 +90,390 @@ struct App {
     frame_durations: VecDeque<u64>,
     keys: HashSet<Key>,
     modifiers: ModifiersState,
+    themes: Vec<Theme>,
+    theme_index: usize,
+    line_style: LineStyle,
+    document_path: Option<PathBuf>,
+    /// How the document is written, which is the format it was read in.
+    document_format: document::Format,
+    command_history: Vec<String>,
+    /// The result of the last command, shown in the bottom line until the next key press.
+    message: Option<String>,
+    exit_requested: bool,
+    marks: BTreeMap<char, Coordinates>,
+    /// Set when the next key press names a mark instead of being translated.
+    pending_name: Option<NamePrompt>,
+    /// The count typed before the next command, if any.
+    count: Option<usize>,
+    /// The last actions that changed the drawing, with the count they were run with.
+    last_change: Option<(Vec<Action>, Option<usize>)>,
+    /// The direction of the last cursor movement, along which counted drawing goes.
+    last_direction: Direction,
+    config_path: Option<PathBuf>,
+    macros: BTreeMap<char, Vec<Action>>,
+    /// The register being recorded into and the actions recorded so far.
+    recording: Option<(char, Vec<Action>)>,
+    /// How many macros are currently being played inside each other.
+    macro_depth: usize,
+    /// How many files are currently being sourced inside each other.
+    source_depth: usize,
+    /// Whether typing in Text mode shifts the rest of the row instead of overwriting it.
+    insert_mode: bool,
+    /// The column where the ongoing run of typing began, if any.
+    text_column: Option<isize>,
+    tab_stops: TabStops,
+    /// The text being composed by the input method and the position of its caret, in characters.
+    preedit: Option<(String, usize)>,
+    /// The last position given to the input method for its popup.
+    ime_cursor_area: Option<(isize, isize)>,
+    /// Where the rectangle or connector being drawn starts, the cursor being its other end.
+    anchor: Option<Coordinates>,
+    arrowhead: Arrowhead,
+    /// The layout new text boxes get.
+    text_layout: TextLayout,
+    /// The text box or table cell being typed into in Text mode and the byte offset of the caret in
+    /// its text.
+    editing: Option<(TextTarget, usize)>,
+    /// The length of the horizontal lines leading to the children of trees.
+    tree_branch: usize,
+    /// The text being searched for, if any.
+    search: Option<String>,
+    search_columns: bool,
+    /// The characters last picked in the palette, the latest first.
+    recent_chars: Vec<char>,
+    /// The characters shown first in the palette, saved to the configuration file.
+    favourites: Vec<char>,
 }
 
 impl App {
-    pub fn new(window: Rc<Window>, font: Font, font_size: f32, key_map: Box<dyn KeyMap>) -> Self {
-        let context = Context::new(window.clone()).unwrap();
-        let surface = Surface::new(&context, window.clone()).unwrap();
-
-        let size = window.inner_size();
-        let (width, height) = (size.width as usize, size.height as usize);
+    pub fn new(
+        window: Rc<Window>,
+        font: Font,
+        font_size: f32,
+        key_map: Box<dyn KeyMap>,
+        gamma_correct: bool,
+        config: Config,
+        theme_index: usize,
+    ) -> Self {
+        let mut canvas = Canvas::new(
+            window.clone(),
+            font,
+            font_size,
+            gamma_correct,
+            config.themes[theme_index].clone(),
+        );
+        canvas.guide_spacing = config.guide_spacing;
 
         Self {
             window,
             key_map,
-            canvas: Canvas::new(font, font_size, surface, width, height),
+            canvas,
             input_mode: InputMode::Box(BoxMode),
             frame_durations: VecDeque::with_capacity(64),
             keys: HashSet::new(),
             modifiers: ModifiersState::empty(),
+            themes: config.themes,
+            theme_index,
+            line_style: LineStyle::Light,
+            document_path: None,
+            document_format: document::Format::Native,
+            command_history: Vec::new(),
+            message: config.warnings.first().cloned(),
+            exit_requested: false,
+            marks: BTreeMap::new(),
+            pending_name: None,
+            count: None,
+            last_change: None,
+            last_direction: Direction::Right,
+            config_path: config.path,
+            macros: config.macros,
+            recording: None,
+            macro_depth: 0,
+            source_depth: 0,
+            insert_mode: false,
+            text_column: None,
+            tab_stops: config.tab_stops,
+            anchor: None,
+            arrowhead: config.arrowhead,
+            text_layout: config.text_layout,
+            editing: None,
+            tree_branch: config.tree_branch,
+            search: None,
+            search_columns: config.search_columns,
+            recent_chars: Vec::new(),
+            favourites: config.favourites,
+            preedit: None,
+            ime_cursor_area: None,
         }
     }
 
     fn handle_action(&mut self, action: &Action) {
+        if let Some((_, recorded)) = &mut self.recording {
+            if action.is_recorded() {
+                recorded.push(action.clone());
+            }
+        }
+
+        // A run of typing is undone at once, and ends with anything else than typing.
+        match (self.text_column.is_some(), action.is_typing()) {
+            (false, true) => {
+                self.text_column = Some(self.canvas.draw_area.cursor_absolute_position().x);
+                self.canvas.draw_area.begin_undo_group();
+            }
+            (true, false) => {
+                self.text_column = None;
+                self.canvas.draw_area.end_undo_group();
+            }
+            _ => {}
+        }
+
+        if self.edit_text(action) {
+            return;
+        }
+
         match action {
-            Action::CursorLeft => self.canvas.draw_area.move_cursor(Direction::Left),
-            Action::CursorRight => self.canvas.draw_area.move_cursor(Direction::Right),
-            Action::CursorUp => self.canvas.draw_area.move_cursor(Direction::Up),
-            Action::CursorDown => self.canvas.draw_area.move_cursor(Direction::Down),
-            Action::DrawCharAtCursor(c) => self.canvas.draw_area.write_at_cursor(*c),
+            Action::CursorLeft => self.move_cursor(Direction::Left, 1),
+            Action::CursorRight => self.move_cursor(Direction::Right, 1),
+            Action::CursorUp => self.move_cursor(Direction::Up, 1),
+            Action::CursorDown => self.move_cursor(Direction::Down, 1),
+            Action::MoveCursor(direction, amount) => self.move_cursor(*direction, *amount),
+            Action::DrawCharAtCursor(c) => {
+                let c = match self.input_mode {
+                    InputMode::Box(_) => glyphs::restyle(*c, self.line_style),
+                    _ => *c,
+                };
+
+                self.canvas.draw_area.write_at_cursor(c)
+            }
+            Action::PutChar(c) => self.canvas.draw_area.write_at_cursor(*c),
             Action::DeleteAtCursor => self.canvas.draw_area.erase_at_cursor(),
+            Action::TypeChar(c) => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                let width = text_area::char_width(*c);
+
+                if self.insert_mode && width > 0 {
+                    self.canvas
+                        .draw_area
+                        .shift_row(cursor.y, cursor.x, width as isize);
+                }
+
+                // Combining marks are added to the character on the left of the cursor.
+                self.canvas
+                    .draw_area
+                    .write_str_at(cursor, c.encode_utf8(&mut [0; 4]));
+                self.move_cursor(Direction::Right, width);
+            }
+            Action::NewLine => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                let x = self.text_column.unwrap_or(cursor.x);
+
+                self.canvas
+                    .draw_area
+                    .move_cursor_to((x, cursor.y + 1).into());
+            }
+            Action::Tab => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                let start = self.text_column.unwrap_or(cursor.x);
+                let column = (cursor.x - start).max(0) as usize;
+                let width = (self.tab_stops.next(column) - column) as isize;
+
+                if self.insert_mode {
+                    self.canvas.draw_area.shift_row(cursor.y, cursor.x, width);
+                }
+
+                self.move_cursor(Direction::Right, width as usize);
+            }
+            Action::Backspace => {
+                self.move_cursor(Direction::Left, 1);
+
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                let width = self.canvas.draw_area.width_at(cursor) as isize;
+                self.canvas.draw_area.erase_at_cursor();
+
+                if self.insert_mode {
+                    self.canvas
+                        .draw_area
+                        .shift_row(cursor.y, cursor.x + width, -width);
+                }
+            }
+            Action::Rectangle(fill) => {
+                if let Some((anchor, cursor)) = self.anchor_or_commit() {
+                    draw::rectangle(
+                        &mut self.canvas.draw_area,
+                        BoundingBox::spanning(anchor, cursor),
+                        self.line_style,
+                        *fill,
+                    );
+                }
+            }
+            Action::Connector => {
+                if let Some((anchor, cursor)) = self.anchor_or_commit() {
+                    let objects = self.canvas.draw_area.objects();
+                    // Ends on a shape are bound to its side facing the other end
+                    let end = |position: Coordinates, other: Coordinates| {
+                        objects
+                            .shape_at(position)
+                            .map(|id| Endpoint::Side(id, objects.shapes[&id].side_towards(other)))
+                    };
+
+                    let routed = match (end(anchor, cursor), end(cursor, anchor)) {
+                        (None, None) => draw::connector(
+                            &mut self.canvas.draw_area,
+                            anchor,
+                            cursor,
+                            self.line_style,
+                            self.arrowhead,
+                            &[],
+                        )
+                        .is_some(),
+                        (from, to) => {
+                            let mut objects = objects.clone();
+                            objects.links.push(Link {
+                                from: from.unwrap_or(Endpoint::Cell(anchor)),
+                                to: to.unwrap_or(Endpoint::Cell(cursor)),
+                                style: self.line_style,
+                                arrowhead: self.arrowhead,
+                                label: String::new(),
+                                path: Vec::new(),
+                            });
+                            objects::update(&mut self.canvas.draw_area, objects);
+
+                            self.canvas
+                                .draw_area
+                                .objects()
+                                .links
+                                .last()
+                                .is_some_and(|l| !l.path.is_empty())
+                        }
+                    };
+
+                    if !routed {
+                        self.message = Some("no route to the cursor".to_string());
+                    }
+                }
+            }
+            Action::AddShape => {
+                if let Some((anchor, cursor)) = self.anchor_or_commit() {
+                    let mut objects = self.canvas.draw_area.objects().clone();
+                    objects.shapes.insert(
+                        objects.next_id(),
+                        Shape {
+                            bounds: BoundingBox::spanning(anchor, cursor),
+                            label: String::new(),
+                            style: self.line_style,
+                            text: None,
+                        },
+                    );
+                    objects::update(&mut self.canvas.draw_area, objects);
+                }
+            }
+            Action::MoveShape(direction) => {
+                if self.edit_shape(|shape| shape.bounds.top_left += direction.vector()) {
+                    self.move_cursor(*direction, 1);
+                }
+            }
+            Action::ResizeShape(direction, grow) => {
+                self.edit_shape(|shape| shape.resize(*direction, *grow));
+            }
+            Action::SetLabel(label) => {
+                self.edit_shape(|shape| shape.label = label.clone());
+            }
+            Action::AddTextBox => {
+                let mut objects = self.canvas.draw_area.objects().clone();
+                let id = objects.next_id();
+
+                objects.shapes.insert(
+                    id,
+                    Shape {
+                        bounds: BoundingBox::new(
+                            self.canvas.draw_area.cursor_absolute_position(),
+                            0,
+                            0,
+                        ),
+                        label: String::new(),
+                        style: self.line_style,
+                        text: Some(self.text_layout),
+                    },
+                );
+                objects::update(&mut self.canvas.draw_area, objects);
+
+                self.set_input_mode(InputMode::Text(TextMode));
+                self.editing = Some((TextTarget::Shape(id), 0));
+                self.place_caret();
+            }
+            Action::SetTextWidth(width) => self.set_text_layout(|l| l.width = (*width).max(1)),
+            Action::SetTextAlign(align) => self.set_text_layout(|l| l.align = *align),
+            Action::SetTextPadding(padding) => self.set_text_layout(|l| l.padding = *padding),
+            Action::DeleteShape => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                let mut objects = self.canvas.draw_area.objects().clone();
+
+                match objects.shape_at(cursor) {
+                    Some(id) => {
+                        objects.remove_shape(id);
+                        objects::update(&mut self.canvas.draw_area, objects);
+                    }
+                    None => self.message = Some("no shape under the cursor".to_string()),
+                }
+            }
+            Action::AddTable(rows, columns) => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                self.add_table(Table::new(cursor, *rows, *columns, self.line_style));
+            }
+            Action::ImportTable(path) => match std::fs::read_to_string(path) {
+                Ok(content) => {
+                    let tsv = path.extension().is_some_and(|e| e == "tsv")
+                        || content.lines().next().is_some_and(|l| l.contains('\t'));
+                    let rows = table::parse_delimited(&content, if tsv { '\t' } else { ',' });
+                    let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+                    self.add_table(Table::from_rows(cursor, rows, self.line_style));
+                }
+                Err(e) => self.message = Some(format!("{}: {e}", path.display())),
+            },
+            Action::EditTable(edit) => self.edit_table(*edit),
+            Action::InsertTree(path) => match std::fs::read_to_string(path) {
+                Ok(content) => {
+                    let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+                    tree::draw(
+                        &mut self.canvas.draw_area,
+                        cursor,
+                        &tree::parse(&content),
+                        self.line_style,
+                        self.tree_branch,
+                    );
+                }
+                Err(e) => self.message = Some(format!("{}: {e}", path.display())),
+            },
+            Action::ExtractTree(path) => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+                self.message = Some(match tree::extract(&self.canvas.draw_area, cursor) {
+                    Some(root) => match std::fs::write(path, tree::to_indented(&[root])) {
+                        Ok(()) => format!("tree written to {}", path.display()),
+                        Err(e) => format!("{}: {e}", path.display()),
+                    },
+                    None => "no tree at the cursor".to_string(),
+                });
+            }
+            Action::InsertSequence(path) => self.insert_sequence(path),
+            Action::ExtractSequence(path) => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                let objects = self.canvas.draw_area.objects();
+
+                self.message = Some(match objects.sequence_at(cursor) {
+                    Some(id) => match std::fs::write(path, &objects.sequences[&id].source) {
+                        Ok(()) => format!("sequence diagram source written to {}", path.display()),
+                        Err(e) => format!("{}: {e}", path.display()),
+                    },
+                    None => "no sequence diagram under the cursor".to_string(),
+                });
+            }
+            Action::SetTreeBranch(length) => self.tree_branch = (*length).max(1),
+            Action::SetArrowhead(arrowhead) => self.arrowhead = *arrowhead,
+            Action::ToggleInsert => self.insert_mode = !self.insert_mode,
+            Action::SetTabStops(stops) => self.tab_stops = stops.clone(),
             Action::ReduceFontSize => {
                 if self.canvas.font_size() > 6.0 {
                     self.canvas.set_font_size(self.canvas.font_size() - 2.0);
@@ -75,22 +484,975 @@ impl App {
             }
             Action::Undo => self.canvas.draw_area.undo(),
             Action::Redo => self.canvas.draw_area.redo(),
-            Action::Transition(mode) => self.input_mode = mode.clone(),
+            Action::Transition(mode) => self.set_input_mode(mode.clone()),
+            Action::NextTheme => {
+                self.theme_index = (self.theme_index + 1) % self.themes.len();
+                self.canvas.theme = self.themes[self.theme_index].clone();
+            }
+            Action::OpenCommandLine(text) => {
+                let mut command_mode = CommandMode::new(self.input_mode.clone());
+                command_mode.buffer = text.clone();
+
+                self.set_input_mode(InputMode::Command(command_mode))
+            }
+            Action::OpenPalette(query) => {
+                let palette = Palette::new(query, &self.recent_chars, &self.favourites);
+
+                self.set_input_mode(InputMode::Palette(PaletteMode {
+                    palette,
+                    previous: Box::new(self.input_mode.clone()),
+                }))
+            }
+            Action::GoTo(position) => self.canvas.draw_area.move_cursor_to(*position),
+            Action::AwaitName(operation) => self.pending_name = Some(*operation),
+            Action::SetMark(name) => {
+                self.marks
+                    .insert(*name, self.canvas.draw_area.cursor_absolute_position());
+            }
+            Action::JumpToMark(name) => match self.marks.get(name) {
+                Some(position) => self.canvas.draw_area.move_cursor_to(*position),
+                None => self.message = Some(format!("mark `{name}` not set")),
+            },
+            Action::NextRegion | Action::PreviousRegion => {
+                let forward = matches!(action, Action::NextRegion);
+
+                if let Some(position) = self.canvas.draw_area.region_from_cursor(forward) {
+                    self.canvas.draw_area.move_cursor_to(position);
+                }
+            }
+            Action::SetLineStyle(style) => self.line_style = *style,
+            Action::EditGrid(edit) => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+                grid::edit(&mut self.canvas.draw_area, cursor, *edit);
+
+                for position in self.marks.values_mut() {
+                    *position = edit.moved(cursor, *position);
+                }
+            }
+            Action::Find(pattern) => {
+                self.search = (!pattern.is_empty()).then(|| pattern.clone());
+                self.find_next(true);
+            }
+            Action::FindNext => self.find_next(true),
+            Action::FindPrevious => self.find_next(false),
+            Action::ReplaceAll(replacement) => match &self.search {
+                Some(pattern) => {
+                    let rectangle = self.rectangle();
+                    let count = search::replace(
+                        &mut self.canvas.draw_area,
+                        pattern,
+                        replacement,
+                        self.search_columns,
+                        rectangle,
+                    );
+                    self.message = Some(format!("{count} replaced"));
+                }
+                None => self.message = Some("nothing searched for".to_string()),
+            },
+            Action::SetSearchColumns(columns) => self.search_columns = *columns,
+            Action::NextIssue => self.next_issue(true),
+            Action::PreviousIssue => self.next_issue(false),
+            Action::FixIssues => {
+                let count = lint::fix(&mut self.canvas.draw_area);
+                self.message = Some(format!("{count} issues fixed"));
+            }
+            Action::Convert(style) => self.convert(*style),
+            Action::Fill(c) => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+                if draw::fill(&mut self.canvas.draw_area, cursor, *c).is_none() {
+                    self.message = Some(format!(
+                        "region larger than {} cells, not filled",
+                        draw::FILL_LIMIT
+                    ));
+                }
+            }
+            Action::ClearRegion => {
+                let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+                if draw::clear_region(&mut self.canvas.draw_area, cursor).is_none() {
+                    self.message = Some("no closed box around the cursor".to_string());
+                }
+            }
+            Action::Transform(transform, keep_text) => match self.rectangle() {
+                Some(rectangle) => {
+                    let area = &mut self.canvas.draw_area;
+                    let transformed = transform::transform(area, rectangle, *transform, *keep_text);
+
+                    // The rectangle stays selected, so that it can be transformed again
+                    self.anchor = Some(transformed.top_left);
+                    area.move_cursor_to(transformed.bottom_right());
+                }
+                None => self.message = Some("no rectangle to transform".to_string()),
+            },
+            Action::SetFontSize(size) => {
+                if *size >= 6.0 {
+                    self.canvas.set_font_size(*size);
+                } else {
+                    self.message = Some("font size too small".to_string());
+                }
+            }
+            Action::SetGuideSpacing(spacing) => self.canvas.guide_spacing = *spacing,
+            Action::SetTheme(name) => match self.themes.iter().position(|t| &t.name == name) {
+                Some(i) => {
+                    self.theme_index = i;
+                    self.canvas.theme = self.themes[i].clone();
+                }
+                None => self.message = Some(format!("unknown theme `{name}`")),
+            },
+            Action::Save(path) => {
+                self.save(path.as_ref());
+            }
+            Action::SaveAndQuit(path) => {
+                if self.save(path.as_ref()) {
+                    self.exit_requested = true;
+                }
+            }
+            Action::Open(path, force) => {
+                if *force || !self.refuse_unsaved() {
+                    self.open(path);
+                }
+            }
+            Action::ImportDot(path, force) => {
+                if *force || !self.refuse_unsaved() {
+                    self.import_dot(path);
+                }
+            }
+            Action::Export(format, path) => {
+                let exported = export::export(
+                    *format,
+                    self.canvas.draw_area.graphemes(),
+                    &self.canvas.theme,
+                );
+
+                self.message = Some(match std::fs::write(path, exported) {
+                    Ok(()) => format!("exported to {}", path.display()),
+                    Err(e) => format!("{}: {e}", path.display()),
+                });
+            }
+            Action::Source(path) => self.source(path),
+            Action::Quit(force) => {
+                if *force || !self.refuse_unsaved() {
+                    self.exit_requested = true;
+                }
+            }
+            Action::Repeat => self.perform(&[Action::Repeat], None),
+            Action::ToggleRecording => match self.recording.take() {
+                Some((register, actions)) => {
+                    self.message = Some(format!("recorded @{register}"));
+                    self.macros.insert(register, actions);
+                }
+                None => self.pending_name = Some(NamePrompt::RecordMacro),
+            },
+            Action::RecordMacro(register) => self.recording = Some((*register, Vec::new())),
+            Action::PlayMacro(register) => self.play_macro(*register),
+            Action::BindChar(register, c) => {
+                self.macros.insert(*register, vec![Action::PutChar(*c)]);
+                self.message = Some(format!("`{c}` bound to @{register}"));
+            }
+            Action::SaveMacros => {
+                self.message = Some(match &self.config_path {
+                    Some(path) => match Config::save_macros(path, &self.macros) {
+                        Ok(()) => format!("macros saved to {}", path.display()),
+                        Err(e) => e.to_string(),
+                    },
+                    None => "no configuration file".to_string(),
+                });
+            }
         }
     }
 
-    pub fn handle_raw_key(&mut self, key: Key, _modifiers: ModifiersState) -> Vec<Action> {
-        match &mut self.input_mode {
-            InputMode::Box(_) | InputMode::Color(_) => vec![],
+    fn play_macro(&mut self, register: char) {
+        /// Stops macros that play themselves, directly or not.
+        const MAX_DEPTH: usize = 16;
+
+        let Some(actions) = self.macros.get(&register).cloned() else {
+            self.message = Some(format!("macro @{register} not recorded"));
+            return;
+        };
+
+        if self.macro_depth >= MAX_DEPTH {
+            self.message = Some(format!("macro @{register} nested too deeply"));
+            return;
+        }
+
+        self.macro_depth += 1;
+
+        for action in &actions {
+            self.handle_action(action);
+        }
+
+        self.macro_depth -= 1;
+    }
+
+    fn set_input_mode(&mut self, mode: InputMode) {
+        // Composed input is only wanted where text is typed
+        let ime_allowed = matches!(mode, InputMode::Text(_) | InputMode::Command(_));
+
+        if ime_allowed != matches!(self.input_mode, InputMode::Text(_) | InputMode::Command(_)) {
+            self.window.set_ime_allowed(ime_allowed);
+            self.preedit = None;
+        }
+
+        if !matches!(mode, InputMode::Text(_) | InputMode::Command(_)) {
+            self.editing = None;
+        }
+
+        self.input_mode = mode;
+    }
+
+    fn handle_ime(&mut self, ime: Ime) {
+        match ime {
+            Ime::Preedit(text, caret) if !text.is_empty() => {
+                // The caret is given as a byte range in the preedit text
+                let caret = caret.map_or(text.chars().count(), |(start, _)| {
+                    text[..start].chars().count()
+                });
+
+                self.preedit = Some((text, caret));
+            }
+            Ime::Preedit(_, _) | Ime::Disabled => self.preedit = None,
+            Ime::Commit(text) => {
+                self.preedit = None;
+
+                let actions = self.handle_raw_key(Key::Character(text.into()), self.modifiers);
+                let count = self.count.take();
+                self.perform(&actions, count);
+            }
+            Ime::Enabled => {}
+        }
+    }
+
+    fn update_ime(&mut self) {
+        match self.input_mode {
             InputMode::Text(_) => {
-                if let Key::Character(s) = key {
-                    s.chars()
-                        .flat_map(|c| [Action::DrawCharAtCursor(c), Action::CursorRight])
-                        .collect()
-                } else {
-                    vec![]
+                self.canvas.preedit = self.preedit.clone();
+
+                let area = self.canvas.cursor_area();
+                let position = (area.top_left.x, area.top_left.y);
+
+                if self.ime_cursor_area != Some(position) {
+                    self.window.set_ime_cursor_area(
+                        PhysicalPosition::new(position.0 as i32, position.1 as i32),
+                        PhysicalSize::new(area.width as u32, area.height as u32),
+                    );
+                    self.ime_cursor_area = Some(position);
                 }
             }
+            _ => self.canvas.preedit = None,
+        }
+    }
+
+    /// Drops the anchor, the name being prompted for and the count being typed. Returns whether
+    /// there was any of them.
+    fn cancel(&mut self) -> bool {
+        let anchor = self.anchor.take().is_some();
+        let pending_name = self.pending_name.take().is_some();
+        let count = self.count.take().is_some();
+
+        anchor || pending_name || count
+    }
+
+    /// Sets the anchor at the cursor if there is none, or takes it along with the cursor position
+    /// to draw between them. Committing on the anchor itself cancels the drawing.
+    fn anchor_or_commit(&mut self) -> Option<(Coordinates, Coordinates)> {
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+        match self.anchor.take() {
+            None => {
+                self.anchor = Some(cursor);
+                None
+            }
+            Some(anchor) => (anchor != cursor).then_some((anchor, cursor)),
+        }
+    }
+
+    /// Applies a Text mode action to the text box or table cell being edited, or to the one under
+    /// the cursor when typing starts on it, returning whether it did.
+    fn edit_text(&mut self, action: &Action) -> bool {
+        if !matches!(self.input_mode, InputMode::Text(_)) {
+            return false;
+        }
+
+        let mut objects = self.canvas.draw_area.objects().clone();
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+        let editing = self.editing.or_else(|| {
+            let target = objects.text_target_at(cursor)?;
+            action
+                .is_typing()
+                .then(|| (target, objects.text(target).map_or(0, String::len)))
+        });
+
+        let Some((target, caret)) = editing else {
+            return false;
+        };
+
+        // New lines and tabs go to the next cell of tables, as their cells hold a single line
+        if let TextTarget::Cell { table, row, column } = target {
+            let next = match action {
+                Action::NewLine => Some((row + 1, column)),
+                Action::Tab => Some((row, column + 1)),
+                _ => None,
+            };
+
+            if let Some((row, column)) = next {
+                let next = objects.tables.get(&table).and_then(|t| {
+                    let (row, column) = match column < t.columns() {
+                        true => (row, column),
+                        false => (row + 1, 0),
+                    };
+                    (row < t.rows()).then_some(TextTarget::Cell { table, row, column })
+                });
+
+                if let Some(next) = next {
+                    self.editing = Some((next, objects.text(next).map_or(0, String::len)));
+                    self.place_caret();
+                }
+
+                return true;
+            }
+        }
+
+        let Some(text) = objects.text_mut(target) else {
+            self.editing = None;
+            return false;
+        };
+
+        // Undoing may have shortened the text
+        let caret = if text.is_char_boundary(caret) {
+            caret
+        } else {
+            text.len()
+        };
+
+        let caret = match edit_string(text, caret, action, &self.tab_stops) {
+            Some(caret) => caret,
+            None if matches!(
+                action,
+                Action::CursorUp
+                    | Action::CursorDown
+                    | Action::MoveCursor(..)
+                    | Action::GoTo(_)
+                    | Action::JumpToMark(_)
+                    | Action::NextRegion
+                    | Action::PreviousRegion
+            ) =>
+            {
+                // Moving elsewhere leaves the text box
+                self.editing = None;
+                return false;
+            }
+            None => return false,
+        };
+
+        self.editing = Some((target, caret));
+
+        if objects != *self.canvas.draw_area.objects() {
+            objects::update(&mut self.canvas.draw_area, objects);
+        }
+
+        self.place_caret();
+        true
+    }
+
+    /// Moves the cursor to the caret of the text box or table cell being edited.
+    fn place_caret(&mut self) {
+        if let Some((target, caret)) = self.editing {
+            if let Some(position) = self
+                .canvas
+                .draw_area
+                .objects()
+                .caret_position(target, caret)
+            {
+                self.canvas.draw_area.move_cursor_to(position);
+            }
+        }
+    }
+
+    /// Changes the layout of new text boxes, and of the one being edited if any.
+    fn set_text_layout(&mut self, change: impl Fn(&mut TextLayout)) {
+        change(&mut self.text_layout);
+
+        let Some((TextTarget::Shape(id), _)) = self.editing else {
+            return;
+        };
+
+        let mut objects = self.canvas.draw_area.objects().clone();
+
+        if let Some(layout) = objects.shapes.get_mut(&id).and_then(|s| s.text.as_mut()) {
+            change(layout);
+            objects::update(&mut self.canvas.draw_area, objects);
+            self.place_caret();
+        }
+    }
+
+    /// Adds the sequence diagram read from `path` at the cursor, or replaces the one under the
+    /// cursor with it, drawn in the current line style.
+    fn insert_sequence(&mut self, path: &Path) {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                self.message = Some(format!("{}: {e}", path.display()));
+                return;
+            }
+        };
+
+        if let Err(e) = sequence::check(&source) {
+            self.message = Some(format!("{}: {e}", path.display()));
+            return;
+        }
+
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+        let mut objects = self.canvas.draw_area.objects().clone();
+        let (id, origin) = match objects.sequence_at(cursor) {
+            Some(id) => (id, objects.sequences[&id].origin),
+            None => (objects.next_sequence_id(), cursor),
+        };
+
+        objects.sequences.insert(
+            id,
+            Sequence {
+                origin,
+                source,
+                style: self.line_style,
+                arrowhead: self.arrowhead,
+            },
+        );
+        objects::update(&mut self.canvas.draw_area, objects);
+    }
+
+    /// The size in cells of the palette panel, which covers the drawing but for a margin.
+    fn palette_size(&self) -> (usize, usize) {
+        let view = self.canvas.draw_area.bounding_box;
+
+        (view.width.saturating_sub(4), view.height.saturating_sub(4))
+    }
+
+    /// The characters of the palette shown over the drawing, if it is open.
+    fn palette_overlay(&mut self) -> Option<Overlay> {
+        let (width, height) = self.palette_size();
+        let InputMode::Palette(PaletteMode { palette, .. }) = &mut self.input_mode else {
+            return None;
+        };
+
+        let columns = width / PALETTE_CELL;
+        let top = palette.scroll(columns, height);
+        let mut area = TextArea::new(width, height);
+        let mut selected = None;
+
+        for (y, row) in palette
+            .rows(columns)
+            .into_iter()
+            .skip(top)
+            .take(height)
+            .enumerate()
+        {
+            let y = y as isize;
+
+            match row {
+                Row::Title(title) => {
+                    for (x, c) in title.chars().enumerate() {
+                        area.write_at((x as isize, y).into(), c);
+                    }
+                }
+                Row::Chars(first, chars) => {
+                    for (i, c) in chars.iter().enumerate() {
+                        let position = ((i * PALETTE_CELL) as isize, y).into();
+                        area.write_at(position, *c);
+
+                        if first + i == palette.selected {
+                            selected = Some(position);
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(Overlay { area, selected })
+    }
+
+    /// The matches of the search, if any.
+    fn matches(&self) -> Vec<BoundingBox> {
+        self.search.as_ref().map_or_else(Vec::new, |pattern| {
+            search::find(&self.canvas.draw_area, pattern, self.search_columns)
+        })
+    }
+
+    /// Moves the cursor to the next match of the search, or the previous one.
+    fn find_next(&mut self, forward: bool) {
+        let Some(pattern) = &self.search else {
+            return;
+        };
+
+        let matches = self.matches();
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+        match search::next(&matches, cursor, forward) {
+            Some(position) => {
+                self.canvas.draw_area.move_cursor_to(position);
+                self.message = Some(format!("{} matches", matches.len()));
+            }
+            None => self.message = Some(format!("`{pattern}` not found")),
+        }
+    }
+
+    /// Moves the cursor to the next glyph whose lines don't match its neighbours, or the previous
+    /// one, telling what is wrong with it.
+    fn next_issue(&mut self, forward: bool) {
+        let issues = lint::check(&self.canvas.draw_area);
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+
+        self.message = Some(match lint::next(&issues, cursor, forward) {
+            Some(i) => {
+                let issue = &issues[i];
+                self.canvas.draw_area.move_cursor_to(issue.position);
+                format!("issue {}/{}: {issue}", i + 1, issues.len())
+            }
+            None => "no issues".to_string(),
+        });
+    }
+
+    /// Converts the lines in the rectangle, or in the whole drawing, to `style`, along with the
+    /// objects drawn there, as a single undo step.
+    fn convert(&mut self, style: LineStyle) {
+        let region = self.rectangle();
+        let arrowhead = match (style, self.arrowhead) {
+            (LineStyle::Ascii, _) => Arrowhead::Ascii,
+            // Converting to box drawing is never meant to keep ASCII arrowheads
+            (_, Arrowhead::Ascii) => Arrowhead::Triangle,
+            (_, arrowhead) => arrowhead,
+        };
+        let within = |b: BoundingBox| region.is_none_or(|r| b.cells().any(|c| r.contains(&c)));
+
+        let mut objects = self.canvas.draw_area.objects().clone();
+
+        for shape in objects.shapes.values_mut().filter(|s| within(s.bounds)) {
+            shape.style = style;
+        }
+        for table in objects.tables.values_mut().filter(|t| within(t.bounds())) {
+            table.style = style;
+        }
+        for sequence in objects.sequences.values_mut() {
+            if within(sequence.bounds()) {
+                sequence.style = style;
+                sequence.arrowhead = arrowhead;
+            }
+        }
+        for link in &mut objects.links {
+            if region.is_none_or(|r| link.path.iter().any(|c| r.contains(c))) {
+                link.style = style;
+                link.arrowhead = match link.arrowhead {
+                    Arrowhead::None => Arrowhead::None,
+                    _ => arrowhead,
+                };
+            }
+        }
+
+        let area = &mut self.canvas.draw_area;
+        area.begin_undo_group();
+        objects::update(area, objects);
+        convert::convert(area, region, style, arrowhead);
+        area.end_undo_group();
+
+        self.anchor = None;
+    }
+
+    fn add_table(&mut self, table: Table) {
+        let mut objects = self.canvas.draw_area.objects().clone();
+        objects.tables.insert(objects.next_table_id(), table);
+        objects::update(&mut self.canvas.draw_area, objects);
+    }
+
+    /// Inserts or deletes a row or column of the table under the cursor, relatively to the cell
+    /// under it, removing the table along with its last row or column.
+    fn edit_table(&mut self, edit: TableEdit) {
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+        let mut objects = self.canvas.draw_area.objects().clone();
+
+        let Some(id) = objects.table_at(cursor) else {
+            self.message = Some("no table under the cursor".to_string());
+            return;
+        };
+        let table = objects.tables.get_mut(&id).unwrap();
+        let Some((row, column)) = table.cell_at(cursor) else {
+            self.message = Some("no table cell under the cursor".to_string());
+            return;
+        };
+
+        let kept = match edit {
+            TableEdit::InsertRow => {
+                table.insert_row(row + 1);
+                true
+            }
+            TableEdit::InsertColumn => {
+                table.insert_column(column + 1);
+                true
+            }
+            TableEdit::DeleteRow => table.remove_row(row),
+            TableEdit::DeleteColumn => table.remove_column(column),
+        };
+
+        if !kept {
+            objects.tables.remove(&id);
+        }
+
+        objects::update(&mut self.canvas.draw_area, objects);
+    }
+
+    /// Changes the shape under the cursor and redraws the objects, returning whether there was one.
+    fn edit_shape(&mut self, edit: impl FnOnce(&mut Shape)) -> bool {
+        let cursor = self.canvas.draw_area.cursor_absolute_position();
+        let mut objects = self.canvas.draw_area.objects().clone();
+
+        let Some(shape) = objects
+            .shape_at(cursor)
+            .and_then(|id| objects.shapes.get_mut(&id))
+        else {
+            self.message = Some("no shape under the cursor".to_string());
+            return false;
+        };
+
+        edit(shape);
+        objects::update(&mut self.canvas.draw_area, objects);
+        true
+    }
+
+    /// The rectangle between the anchor and the cursor, if an anchor is set.
+    fn rectangle(&self) -> Option<BoundingBox> {
+        self.anchor.map(|anchor| {
+            BoundingBox::spanning(anchor, self.canvas.draw_area.cursor_absolute_position())
+        })
+    }
+
+    fn move_cursor(&mut self, direction: Direction, amount: usize) {
+        self.canvas.draw_area.move_cursor_by(direction, amount);
+        self.last_direction = direction;
+    }
+
+    pub fn handle_key_press(&mut self, key: Key) {
+        self.message = None;
+
+        if self.preedit.is_some() {
+            // The input method is using the keys to compose the text
+            return;
+        }
+
+        if matches!(
+            key,
+            Key::Named(NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::AltGraph)
+        ) {
+            // Modifiers only change the next key press
+            return;
+        }
+
+        if let Some(operation) = self.pending_name.take() {
+            if let Some(action) = Self::name_action(operation, &key) {
+                let count = self.count.take();
+                self.perform(&[action], count);
+            }
+
+            return;
+        }
+
+        if let Some(digit) = self.count_digit(&key) {
+            self.count = Some(
+                self.count
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit),
+            );
+            return;
+        }
+
+        let actions = if let Some(actions) =
+            self.key_map
+                .translate(self.input_mode.identifier(), self.modifiers, key.clone())
+        {
+            self.keys.insert(key);
+            actions.to_vec()
+        } else {
+            self.handle_raw_key(key, self.modifiers)
+        };
+
+        if let [action @ Action::AwaitName(_)] = actions.as_slice() {
+            // Keep the count for the action completed by the name
+            self.handle_action(action);
+            return;
+        }
+
+        let count = self.count.take();
+        self.perform(&actions, count);
+    }
+
+    /// The value of `key` as a digit of a count prefix. Counts are typed with the plain digits in
+    /// Box and Color modes, and with Alt held down in Text mode, where digits are text.
+    fn count_digit(&self, key: &Key) -> Option<usize> {
+        let Key::Character(s) = key else {
+            return None;
+        };
+
+        let mut chars = s.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        let digit = c.to_digit(10)? as usize;
+
+        let counting = match self.input_mode {
+            InputMode::Box(_) | InputMode::Color(_) => {
+                !self.modifiers.control_key() && !self.modifiers.alt_key()
+            }
+            InputMode::Text(_) => !self.modifiers.control_key() && self.modifiers.alt_key(),
+            InputMode::Extra(_) | InputMode::Command(_) | InputMode::Palette(_) => false,
+        };
+
+        // A count can't start with a zero
+        (counting && (digit != 0 || self.count.is_some())).then_some(digit)
+    }
+
+    /// Runs `actions` `count` times, remembering them for [`Action::Repeat`] if they change the
+    /// drawing.
+    fn perform(&mut self, actions: &[Action], count: Option<usize>) {
+        if let [Action::Repeat] = actions {
+            if let Some((actions, last_count)) = self.last_change.clone() {
+                self.perform_counted(&actions, count.or(last_count).unwrap_or(1));
+            }
+
+            return;
+        }
+
+        if actions.iter().any(Action::is_change) {
+            self.last_change = Some((actions.to_vec(), count));
+        }
+
+        self.perform_counted(actions, count.unwrap_or(1));
+    }
+
+    fn perform_counted(&mut self, actions: &[Action], count: usize) {
+        let direction = match actions {
+            [Action::CursorLeft] => Some(Direction::Left),
+            [Action::CursorRight] => Some(Direction::Right),
+            [Action::CursorUp] => Some(Direction::Up),
+            [Action::CursorDown] => Some(Direction::Down),
+            _ => None,
+        };
+
+        // A single step stays a plain movement, which moves the caret of a text box being edited
+        if let Some(direction) = direction.filter(|_| count > 1) {
+            self.handle_action(&Action::MoveCursor(direction, count));
+            return;
+        }
+
+        // Drawing the same character several times in place would be pointless, so follow the
+        // last movement instead.
+        let advances = actions
+            .iter()
+            .any(|a| matches!(a, Action::DrawCharAtCursor(_) | Action::PutChar(_)))
+            && !actions.iter().any(|a| {
+                matches!(
+                    a,
+                    Action::CursorLeft
+                        | Action::CursorRight
+                        | Action::CursorUp
+                        | Action::CursorDown
+                )
+            });
+
+        for i in 0..count {
+            if i > 0 && advances {
+                self.move_cursor(self.last_direction, 1);
+            }
+
+            for action in actions {
+                self.handle_action(action);
+            }
+        }
+    }
+
+    /// The action using the mark or register typed with `key`, any key other than a single
+    /// visible character cancelling the operation. Macro registers are letters and digits only.
+    fn name_action(operation: NamePrompt, key: &Key) -> Option<Action> {
+        let Key::Character(s) = key else {
+            return None;
+        };
+
+        let mut chars = s.chars();
+
+        match (operation, chars.next(), chars.next()) {
+            (NamePrompt::SetMark, Some(name), None) if !name.is_whitespace() => {
+                Some(Action::SetMark(name))
+            }
+            (NamePrompt::JumpToMark, Some(name), None) if !name.is_whitespace() => {
+                Some(Action::JumpToMark(name))
+            }
+            (NamePrompt::RecordMacro, Some(name), None) if name.is_alphanumeric() => {
+                Some(Action::RecordMacro(name))
+            }
+            (NamePrompt::PlayMacro, Some(name), None) if name.is_alphanumeric() => {
+                Some(Action::PlayMacro(name))
+            }
+            (NamePrompt::BindChar(c), Some(name), None) if name.is_alphanumeric() => {
+                Some(Action::BindChar(name, c))
+            }
+            _ => None,
+        }
+    }
+
+    /// Tells that the drawing has unsaved changes, if it has. Returns whether it has.
+    fn refuse_unsaved(&mut self) -> bool {
+        let modified = self.canvas.draw_area.is_modified();
+
+        if modified {
+            self.message = Some("no write since last change (add ! to override)".to_string());
+        }
+
+        modified
+    }
+
+    /// Writes the drawing to `path`, or to the file it was last opened from or written to. Returns
+    /// whether it was written.
+    fn save(&mut self, path: Option<&PathBuf>) -> bool {
+        let Some(path) = path.or(self.document_path.as_ref()).cloned() else {
+            self.message = Some("no file name".to_string());
+            return false;
+        };
+
+        let document = Document {
+            graphemes: self
+                .canvas
+                .draw_area
+                .graphemes()
+                .map(|(k, g)| (k, g.to_string()))
+                .collect(),
+            marks: self.marks.clone(),
+            objects: self.canvas.draw_area.objects().clone(),
+            format: self.document_format,
+        };
+
+        match document.save(&path) {
+            Ok(()) => {
+                self.message = Some(match self.document_format {
+                    document::Format::Native => format!("written {}", path.display()),
+                    document::Format::Plain => format!("written {} as plain text", path.display()),
+                });
+                self.canvas.draw_area.mark_saved();
+                self.document_path = Some(path);
+                true
+            }
+            Err(e) => {
+                self.message = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    fn open(&mut self, path: &Path) {
+        if !path.exists() {
+            self.canvas
+                .draw_area
+                .load::<_, &str>([], Objects::default());
+            self.marks.clear();
+            self.message = Some(format!("new file {}", path.display()));
+            self.document_path = Some(path.to_path_buf());
+            self.document_format = document::Format::Native;
+            return;
+        }
+
+        self.message = Some(match Document::load(path) {
+            Ok(document) => {
+                self.canvas
+                    .draw_area
+                    .load(document.graphemes, document.objects);
+                self.marks = document.marks;
+                self.document_path = Some(path.to_path_buf());
+                self.document_format = document.format;
+                format!("opened {}", path.display())
+            }
+            Err(e) => e.to_string(),
+        });
+    }
+
+    /// Replaces the drawing with the graph laid out from the DOT file at `path`, as a new document
+    /// without a file name.
+    fn import_dot(&mut self, path: &Path) {
+        let graph = match std::fs::read_to_string(path) {
+            Ok(source) => dot::parse(&source),
+            Err(e) => Err(e.to_string()),
+        };
+
+        self.message = Some(match graph {
+            Ok(graph) => {
+                // Drawn aside so that the new document starts without history
+                let mut area = TextArea::new(0, 0);
+                objects::update(
+                    &mut area,
+                    dot::layout(&graph, self.line_style, self.arrowhead),
+                );
+
+                self.canvas
+                    .draw_area
+                    .load(area.graphemes(), area.objects().clone());
+                // Nothing was saved of it yet
+                self.canvas.draw_area.mark_modified();
+                self.canvas.draw_area.move_cursor_to((0, 0).into());
+                self.marks.clear();
+                self.document_path = None;
+                self.document_format = document::Format::Native;
+                format!("imported {}", path.display())
+            }
+            Err(e) => format!("{}: {e}", path.display()),
+        });
+    }
+
+    /// Runs every line of the file at `path` as a command.
+    fn source(&mut self, path: &Path) {
+        /// Stops files that source themselves, directly or not.
+        const MAX_DEPTH: usize = 16;
+
+        let commands = match std::fs::read_to_string(path) {
+            Ok(commands) => commands,
+            Err(e) => {
+                self.message = Some(format!("{}: {e}", path.display()));
+                return;
+            }
+        };
+
+        if self.source_depth >= MAX_DEPTH {
+            self.message = Some(format!("{} sourced too deeply", path.display()));
+            return;
+        }
+
+        self.source_depth += 1;
+
+        for (i, line) in commands.lines().enumerate() {
+            let line = line.trim().trim_start_matches(':');
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match command::parse(line) {
+                Ok(actions) => actions.iter().for_each(|a| self.handle_action(a)),
+                Err(e) => {
+                    self.message = Some(format!("{}:{}: {e}", path.display(), i + 1));
+                    break;
+                }
+            }
+        }
+
+        self.source_depth -= 1;
+    }
+
+    pub fn handle_raw_key(&mut self, key: Key, modifiers: ModifiersState) -> Vec<Action> {
+        let palette_columns = self.palette_size().0 / PALETTE_CELL;
+
+        match &mut self.input_mode {
+            InputMode::Box(_) | InputMode::Color(_) => vec![],
+            InputMode::Text(_) => match key {
+                Key::Character(s) => s.chars().map(Action::TypeChar).collect(),
+                Key::Named(NamedKey::Space) => vec![Action::TypeChar(' ')],
+                _ => vec![],
+            },
             InputMode::Extra(e) => match key {
                 Key::Character(c) if c.chars().all(|c| c.is_whitespace()) => {
                     e.buffer_to_actions(&*self.key_map)
@@ -112,6 +1474,174 @@ impl App {
                 }
                 _ => vec![],
             },
+            InputMode::Palette(p) => {
+                let previous = Action::Transition((*p.previous).clone());
+                let selected = p.palette.selected();
+
+                match key {
+                    Key::Named(NamedKey::Escape) => vec![previous],
+                    Key::Named(NamedKey::Enter) => {
+                        let Some(c) = selected else {
+                            return vec![];
+                        };
+
+                        self.recent_chars.retain(|r| *r != c);
+                        self.recent_chars.insert(0, c);
+                        self.recent_chars.truncate(palette::RECENT_LIMIT);
+
+                        if modifiers.shift_key() {
+                            vec![previous, Action::AwaitName(NamePrompt::BindChar(c))]
+                        } else {
+                            vec![previous, Action::PutChar(c)]
+                        }
+                    }
+                    Key::Named(NamedKey::Tab) => {
+                        let Some(c) = selected else {
+                            return vec![];
+                        };
+
+                        match self.favourites.iter().position(|f| *f == c) {
+                            Some(i) => {
+                                self.favourites.remove(i);
+                            }
+                            None => self.favourites.push(c),
+                        }
+
+                        p.palette.update(&self.recent_chars, &self.favourites);
+
+                        if let Some(path) = &self.config_path {
+                            if let Err(e) = Config::save_favourites(path, &self.favourites) {
+                                self.message = Some(e.to_string());
+                            }
+                        }
+
+                        vec![]
+                    }
+                    Key::Named(
+                        named @ (NamedKey::ArrowUp
+                        | NamedKey::ArrowRight
+                        | NamedKey::ArrowDown
+                        | NamedKey::ArrowLeft),
+                    ) => {
+                        let direction = match named {
+                            NamedKey::ArrowUp => Direction::Up,
+                            NamedKey::ArrowRight => Direction::Right,
+                            NamedKey::ArrowDown => Direction::Down,
+                            _ => Direction::Left,
+                        };
+
+                        p.palette.move_selection(direction, palette_columns);
+                        vec![]
+                    }
+                    Key::Named(NamedKey::Backspace) => {
+                        if p.palette.query.pop().is_some() {
+                            p.palette.update(&self.recent_chars, &self.favourites);
+                        }
+
+                        vec![]
+                    }
+                    Key::Named(NamedKey::Space) => {
+                        // Words are only looked for once something is typed after them
+                        p.palette.query.push(' ');
+                        vec![]
+                    }
+                    Key::Character(s) => {
+                        p.palette.query.push_str(&s);
+                        p.palette.update(&self.recent_chars, &self.favourites);
+                        vec![]
+                    }
+                    _ => vec![],
+                }
+            }
+            InputMode::Command(c) => {
+                if key != Key::Named(NamedKey::Tab) {
+                    c.completions = None;
+                }
+
+                match key {
+                    Key::Named(NamedKey::Enter) => {
+                        let command = std::mem::take(&mut c.buffer);
+                        let mut actions = vec![Action::Transition((*c.previous).clone())];
+
+                        if !command.trim().is_empty() {
+                            self.command_history.push(command.clone());
+                        }
+
+                        match command::parse(&command) {
+                            Ok(parsed) => actions.extend(parsed),
+                            Err(e) => self.message = Some(e),
+                        }
+
+                        actions
+                    }
+                    Key::Named(NamedKey::Escape) => {
+                        vec![Action::Transition((*c.previous).clone())]
+                    }
+                    Key::Named(NamedKey::Backspace) => {
+                        if c.buffer.pop().is_none() {
+                            vec![Action::Transition((*c.previous).clone())]
+                        } else {
+                            vec![]
+                        }
+                    }
+                    Key::Named(NamedKey::Tab) => {
+                        let (candidates, index) = c.completions.get_or_insert_with(|| {
+                            let themes = self
+                                .themes
+                                .iter()
+                                .map(|t| t.name.clone())
+                                .collect::<Vec<_>>();
+
+                            (command::complete(&c.buffer, &themes), usize::MAX)
+                        });
+
+                        if !candidates.is_empty() {
+                            *index = index.wrapping_add(1) % candidates.len();
+                            c.buffer = candidates[*index].clone();
+                        }
+
+                        vec![]
+                    }
+                    Key::Named(NamedKey::ArrowUp) => {
+                        let index = match c.history_index {
+                            Some(i) => i.saturating_sub(1),
+                            None => self.command_history.len().saturating_sub(1),
+                        };
+
+                        if let Some(command) = self.command_history.get(index) {
+                            c.history_index = Some(index);
+                            c.buffer = command.clone();
+                        }
+
+                        vec![]
+                    }
+                    Key::Named(NamedKey::ArrowDown) => {
+                        if let Some(i) = c.history_index {
+                            match self.command_history.get(i + 1) {
+                                Some(command) => {
+                                    c.history_index = Some(i + 1);
+                                    c.buffer = command.clone();
+                                }
+                                None => {
+                                    c.history_index = None;
+                                    c.buffer.clear();
+                                }
+                            }
+                        }
+
+                        vec![]
+                    }
+                    Key::Named(NamedKey::Space) => {
+                        c.buffer.push(' ');
+                        vec![]
+                    }
+                    Key::Character(s) => {
+                        c.buffer.push_str(&s);
+                        vec![]
+                    }
+                    _ => vec![],
+                }
+            }
         }
     }
 
@@ -124,6 +1654,10 @@ impl App {
 
                 self.top_line();
                 self.bottom_line();
+                self.update_ime();
+                self.canvas.selection = self.rectangle();
+                self.canvas.highlights = self.matches();
+                self.canvas.overlay = self.palette_overlay();
 
                 match event {
                     Event::NewEvents(StartCause::ResumeTimeReached {
@@ -158,47 +1692,61 @@ impl App {
                             self.canvas.render();
                         }
                     }
+                    Event::WindowEvent {
+                        window_id,
+                        event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                    } if window_id == self.window.id() => {
+                        // The new physical size arrives with the `Resized` event that follows.
+                        self.canvas.set_scale_factor(scale_factor);
+                        self.window.request_redraw();
+                    }
+                    Event::WindowEvent {
+                        event: WindowEvent::CloseRequested,
+                        window_id,
+                    } if window_id == self.window.id() => {
+                        self.handle_action(&Action::Quit(false));
+                        self.window.request_redraw();
+                    }
                     Event::WindowEvent {
                         event:
-                            WindowEvent::CloseRequested
-                            | WindowEvent::KeyboardInput {
+                            WindowEvent::KeyboardInput {
                                 event:
                                     KeyEvent {
                                         logical_key: Key::Named(NamedKey::Escape),
+                                        state: ElementState::Pressed,
                                         ..
                                     },
                                 ..
                             },
                         window_id,
-                    } if window_id == self.window.id() => {
-                        elwt.exit();
+                    } if window_id == self.window.id()
+                        && self.preedit.is_none()
+                        && !matches!(
+                            self.input_mode,
+                            InputMode::Command(_) | InputMode::Palette(_)
+                        ) =>
+                    {
+                        if !self.cancel() {
+                            self.handle_action(&Action::Quit(false));
+                        }
+                        self.window.request_redraw();
                     }
                     Event::WindowEvent {
                         event: WindowEvent::KeyboardInput { event, .. },
                         window_id,
                     } if window_id == self.window.id() => match event.state {
-                        ElementState::Pressed => {
-                            if let Some(actions) = self.key_map.translate(
-                                self.input_mode.identifier(),
-                                self.modifiers,
-                                event.logical_key.clone(),
-                            ) {
-                                self.keys.insert(event.logical_key);
-                                for action in actions {
-                                    self.handle_action(action)
-                                }
-                            } else {
-                                for action in
-                                    &self.handle_raw_key(event.logical_key, self.modifiers)
-                                {
-                                    self.handle_action(action)
-                                }
-                            }
-                        }
+                        ElementState::Pressed => self.handle_key_press(event.logical_key),
                         ElementState::Released => {
                             self.keys.remove(&event.logical_key);
                         }
                     },
+                    Event::WindowEvent {
+                        window_id,
+                        event: WindowEvent::Ime(ime),
+                    } if window_id == self.window.id() => {
+                        self.handle_ime(ime);
+                        self.window.request_redraw();
+                    }
                     Event::WindowEvent {
                         window_id,
                         event: WindowEvent::ModifiersChanged(modifiers),
@@ -208,6 +1756,10 @@ impl App {
                     _ => {}
                 }
 
+                if self.exit_requested {
+                    elwt.exit();
+                }
+
                 self.frame_durations
                     .push_back(start.elapsed().as_nanos() as u64);
                 if self.frame_durations.len() > 64 {
@@ -221,16 +1773,34 @@ impl App {
         self.canvas.top_line.reset_cursor();
         self.canvas.top_line.clear();
         self.canvas.top_line.write_string_at_cursor(&format!(
-            "X = {}, Y = {}, mode = {}, keys = [{}]",
+            "X = {}, Y = {}, mode = {}, style = {}, keys = [{}]{}{}",
             //1.0 / frames,
             self.canvas.draw_area.cursor_absolute_position().x,
             self.canvas.draw_area.cursor_absolute_position().y,
-            self.input_mode,
+            match self.input_mode {
+                InputMode::Text(_)
+                    if matches!(self.editing, Some((TextTarget::Cell { .. }, _))) =>
+                {
+                    "Text (table)".to_string()
+                }
+                InputMode::Text(_) if self.editing.is_some() => "Text (text box)".to_string(),
+                InputMode::Text(_) if self.insert_mode => "Text (insert)".to_string(),
+                _ => self.input_mode.to_string(),
+            },
+            self.line_style,
             self.keys
                 .iter()
                 .map(|k| format!("{k:?}"))
                 .collect::<Vec<_>>()
                 .join(", "),
+            match &self.recording {
+                Some((register, _)) => format!(", recording @{register}"),
+                None => String::new(),
+            },
+            match self.rectangle() {
+                Some(rectangle) => format!(", rectangle {}x{}", rectangle.width, rectangle.height),
+                None => String::new(),
+            },
         ));
     }
 
@@ -238,13 +1808,54 @@ impl App {
         self.canvas.bottom_line.reset_cursor();
         self.canvas.bottom_line.clear();
 
-        if let InputMode::Extra(ExtraMode { buffer }) = &self.input_mode {
-            if !buffer.is_empty() {
+        match &self.input_mode {
+            InputMode::Extra(ExtraMode { buffer }) if !buffer.is_empty() => {
                 self.canvas.bottom_line.write_string_at_cursor(&format!(
                     "Char code: {}",
                     buffer.iter().copied().collect::<String>()
                 ));
             }
+            InputMode::Palette(PaletteMode { palette, .. }) => {
+                let selected = palette.selected().map_or(String::new(), |c| {
+                    format!(
+                        "  U+{:04X} {}",
+                        c as u32,
+                        palette::name(c).unwrap_or_default()
+                    )
+                });
+
+                self.canvas
+                    .bottom_line
+                    .write_string_at_cursor(&format!("Palette: {}{selected}", palette.query));
+            }
+            InputMode::Command(CommandMode { buffer, .. }) => {
+                let preedit = self.preedit.as_ref().map_or("", |(text, _)| text.as_str());
+
+                self.canvas
+                    .bottom_line
+                    .write_string_at_cursor(&format!(":{buffer}{preedit}"));
+            }
+            _ if self.count.is_some() => {
+                self.canvas
+                    .bottom_line
+                    .write_string_at_cursor(&format!("Count: {}", self.count.unwrap()));
+            }
+            _ if self.pending_name.is_some() => {
+                self.canvas
+                    .bottom_line
+                    .write_string_at_cursor(match self.pending_name {
+                        Some(NamePrompt::SetMark) => "Set mark: ",
+                        Some(NamePrompt::JumpToMark) => "Jump to mark: ",
+                        Some(NamePrompt::RecordMacro) => "Record macro: ",
+                        Some(NamePrompt::BindChar(_)) => "Bind to macro: ",
+                        _ => "Play macro: ",
+                    });
+            }
+            _ => {
+                if let Some(message) = &self.message {
+                    self.canvas.bottom_line.write_string_at_cursor(message);
+                }
+            }
         }
     }
 }
@@ -263,24 +1874,166 @@ impl fmt::Display for KeyboardLayout {
     }
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Reports the box drawing glyphs whose lines don't match their neighbours, exiting with an
+    /// error if there are any
+    Check {
+        /// The drawings to check
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
 #[derive(Debug, Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// The path to the font to use
-    font: PathBuf,
+    #[arg(required = true)]
+    font: Option<PathBuf>,
+    /// The drawing to open
+    file: Option<PathBuf>,
     /// Which keyboard layout to use
     #[arg(short, long, default_value_t=KeyboardLayout::Qwerty)]
     keyboard_layout: KeyboardLayout,
+    /// Blend glyphs in linear light instead of sRGB space
+    #[arg(short, long)]
+    gamma_correct: bool,
+    /// The name of the colour theme to start with, `dark` and `light` being built in
+    #[arg(short, long)]
+    theme: Option<String>,
+    /// The path to the configuration file, defaults to `$XDG_CONFIG_HOME/boxdrawed/config.ini`
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+}
+
+/// Prints the issues of each drawing as `path:line:column: message`, returning whether there were
+/// none.
+fn check(files: &[PathBuf]) -> bool {
+    let mut clean = true;
+
+    for path in files {
+        let document = match Document::load(path) {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("error: {e}");
+                clean = false;
+                continue;
+            }
+        };
+
+        let mut area = TextArea::new(0, 0);
+        area.load(document.graphemes, document.objects);
+
+        for issue in lint::check(&area) {
+            let Coordinates { x, y } = issue.position;
+            println!("{}:{}:{}: {issue}", path.display(), y + 1, x + 1);
+            clean = false;
+        }
+    }
+
+    clean
+}
+
+/// Applies a typing or caret movement action to `text` with the caret at byte `caret`, returning
+/// where the caret goes, or `None` if the action is neither.
+fn edit_string(
+    text: &mut String,
+    caret: usize,
+    action: &Action,
+    tab_stops: &TabStops,
+) -> Option<usize> {
+    let previous = text[..caret].grapheme_indices(true).next_back();
+    let next = text[caret..].graphemes(true).next();
+
+    Some(match action {
+        Action::TypeChar(c) => {
+            text.insert(caret, *c);
+            caret + c.len_utf8()
+        }
+        Action::NewLine => {
+            text.insert(caret, '\n');
+            caret + 1
+        }
+        Action::Tab => {
+            let line_start = text[..caret].rfind('\n').map_or(0, |i| i + 1);
+            let column = text_area::columns(&text[line_start..caret]);
+            let spaces = tab_stops.next(column) - column;
+
+            text.insert_str(caret, &" ".repeat(spaces));
+            caret + spaces
+        }
+        Action::Backspace => match previous {
+            Some((start, _)) => {
+                text.replace_range(start..caret, "");
+                start
+            }
+            None => caret,
+        },
+        Action::DeleteAtCursor => {
+            if let Some(next) = next {
+                text.replace_range(caret..caret + next.len(), "");
+            }
+
+            caret
+        }
+        Action::CursorLeft => previous.map_or(caret, |(start, _)| start),
+        Action::CursorRight => next.map_or(caret, |next| caret + next.len()),
+        Action::MoveCursor(Direction::Left, amount) => text[..caret]
+            .grapheme_indices(true)
+            .rev()
+            .take(*amount)
+            .last()
+            .map_or(caret, |(start, _)| start),
+        Action::MoveCursor(Direction::Right, amount) => {
+            caret
+                + text[caret..]
+                    .graphemes(true)
+                    .take(*amount)
+                    .map(str::len)
+                    .sum::<usize>()
+        }
+        _ => return None,
+    })
 }
 
 fn main() {
     let args = Args::parse();
 
+    if let Some(Command::Check { files }) = &args.command {
+        std::process::exit(if check(files) { 0 } else { 1 });
+    }
+
+    let config = match args.config.or_else(Config::default_path) {
+        Some(path) => Config::load(path).unwrap_or_else(|e| {
+            eprintln!("error: invalid configuration: {e}");
+            std::process::exit(1)
+        }),
+        None => Config::default(),
+    };
+
+    for warning in &config.warnings {
+        eprintln!("warning: {warning}");
+    }
+
+    let theme_index = match args.theme.as_ref().or(config.theme.as_ref()) {
+        Some(name) => config.theme_index(name).unwrap_or_else(|| {
+            eprintln!("error: unknown theme `{name}`");
+            std::process::exit(1)
+        }),
+        None => 0,
+    };
+
     let event_loop = EventLoop::new().unwrap();
     let window = Rc::new(WindowBuilder::new().build(&event_loop).unwrap());
 
-    let font =
-        Font::from_bytes(std::fs::read(args.font).unwrap(), FontSettings::default()).unwrap();
+    let font = Font::from_bytes(
+        std::fs::read(args.font.unwrap()).unwrap(),
+        FontSettings::default(),
+    )
+    .unwrap();
 
     let layout: Box<dyn KeyMap> = match args.keyboard_layout {
         KeyboardLayout::Qwerty => Box::new(Qwerty),
@@ -288,7 +2041,52 @@ fn main() {
         KeyboardLayout::Bépo => Box::new(Bépo),
     };
 
-    let app = App::new(window, font, 24.0, layout);
+    let mut app = App::new(
+        window,
+        font,
+        24.0,
+        layout,
+        args.gamma_correct,
+        config,
+        theme_index,
+    );
+
+    if let Some(path) = args.file {
+        app.handle_action(&Action::Open(path, false));
+    }
 
     app.run(event_loop)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_string_at_caret() {
+        let tab_stops = TabStops::default();
+        let mut text = String::new();
+        let mut caret = 0;
+
+        for action in [
+            Action::TypeChar('a'),
+            Action::TypeChar('b'),
+            Action::CursorLeft,
+            Action::TypeChar('c'),
+            Action::MoveCursor(Direction::Left, 5),
+            Action::TypeChar('世'),
+            Action::MoveCursor(Direction::Right, 2),
+            Action::Backspace,
+            Action::CursorRight,
+            Action::TypeChar('d'),
+        ] {
+            caret = edit_string(&mut text, caret, &action, &tab_stops).unwrap();
+        }
+
+        assert_eq!((text.as_str(), caret), ("世abd", 6));
+        assert_eq!(
+            edit_string(&mut text, caret, &Action::CursorUp, &tab_stops),
+            None
+        );
+    }
+}
@@ -0,0 +1,712 @@
+//! The optional object layer: labelled boxes, tables, sequence diagrams and the connectors bound to
+//! the sides of boxes, kept as a model next to the grid they are rasterized into, so that moving a
+//! box re-routes its connectors.
+
+use std::{collections::BTreeMap, fmt, ops::Range, str::FromStr};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    draw::{self, RectangleFill},
+    glyphs::{Arrowhead, LineStyle},
+    sequence::Sequence,
+    table::Table,
+    text_area::{self, BoundingBox, Coordinates, Direction, TextArea},
+};
+
+/// The smallest size of a shape, which has room for its border only.
+const MIN_SIZE: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    pub const ALL: [Align; 3] = [Align::Left, Align::Center, Align::Right];
+}
+
+impl fmt::Display for Align {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+impl FromStr for Align {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Align::ALL
+            .into_iter()
+            .find(|align| align.to_string() == s)
+            .ok_or(())
+    }
+}
+
+/// How the text of a text box is laid out, the border of the box fitting around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextLayout {
+    /// The column at which lines are wrapped.
+    pub width: usize,
+    pub align: Align,
+    /// The blank columns between the text and the left and right sides.
+    pub padding: usize,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            width: 30,
+            align: Align::Left,
+            padding: 1,
+        }
+    }
+}
+
+/// Splits `text` into lines no wider than `width` columns, breaking them at newlines and at the
+/// last space that fits, or anywhere in words too long for a line. The lines are returned as
+/// ranges of `text`, without the newlines and spaces they were broken at.
+pub fn wrap(text: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for paragraph in text.split('\n') {
+        let mut start = offset;
+        let mut column = 0;
+        let mut last_space = None;
+
+        for (i, grapheme) in paragraph.grapheme_indices(true) {
+            let i = offset + i;
+
+            if column + text_area::grapheme_width(grapheme) > width && i > start {
+                if grapheme == " " {
+                    lines.push(start..i);
+                    (start, column, last_space) = (i + 1, 0, None);
+                    continue;
+                } else if let Some(space) = last_space.take() {
+                    lines.push(start..space);
+                    start = space + 1;
+                    column = text_area::columns(&text[start..i]);
+                } else {
+                    lines.push(start..i);
+                    (start, column) = (i, 0);
+                }
+            }
+
+            if grapheme == " " {
+                last_space = Some(i);
+            }
+
+            column += text_area::grapheme_width(grapheme);
+        }
+
+        lines.push(start..offset + paragraph.len());
+        offset += paragraph.len() + 1;
+    }
+
+    lines
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    pub bounds: BoundingBox,
+    /// Shown centered on the middle row of the inside, or laid out by `text` if set.
+    pub label: String,
+    pub style: LineStyle,
+    /// Makes the shape a text box, fitting around its label.
+    pub text: Option<TextLayout>,
+}
+
+impl Shape {
+    /// The cells inside of the border.
+    fn inside(&self) -> BoundingBox {
+        BoundingBox::new(
+            self.bounds.top_left + (1, 1).into(),
+            self.bounds.width.saturating_sub(2),
+            self.bounds.height.saturating_sub(2),
+        )
+    }
+
+    /// Resizes a text box to fit its text.
+    fn fit(&mut self) {
+        if let Some(layout) = self.text {
+            let lines = wrap(&self.label, layout.width);
+            let width = lines
+                .iter()
+                .map(|l| text_area::columns(&self.label[l.clone()]))
+                .max()
+                .unwrap_or(0);
+
+            // Leave room for the cursor after the text
+            self.bounds.width = width.max(1) + 2 * layout.padding + 2;
+            self.bounds.height = lines.len() + 2;
+        }
+    }
+
+    /// The lines of the label, as ranges of it, along with where each one starts on the grid.
+    fn lines(&self) -> Vec<(Coordinates, Range<usize>)> {
+        let inside = self.inside();
+
+        let Some(layout) = self.text else {
+            // A single line, cut short to fit
+            let mut end = 0;
+            let mut column = 0;
+
+            for (i, grapheme) in self.label.grapheme_indices(true) {
+                column += text_area::grapheme_width(grapheme);
+
+                if inside.height == 0 || column > inside.width {
+                    break;
+                }
+
+                end = i + grapheme.len();
+            }
+
+            let start = inside.top_left
+                + (
+                    ((inside.width - text_area::columns(&self.label[..end])) / 2) as isize,
+                    (inside.height.saturating_sub(1) / 2) as isize,
+                )
+                    .into();
+
+            return vec![(start, 0..end)];
+        };
+
+        let lines = wrap(&self.label, layout.width);
+        let widths = lines
+            .iter()
+            .map(|l| text_area::columns(&self.label[l.clone()]))
+            .collect::<Vec<_>>();
+        let width = widths.iter().copied().max().unwrap_or(0);
+
+        lines
+            .into_iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(row, (line, line_width))| {
+                let offset = match layout.align {
+                    Align::Left => 0,
+                    Align::Center => (width - line_width) / 2,
+                    Align::Right => width - line_width,
+                };
+
+                (
+                    inside.top_left + ((layout.padding + offset) as isize, row as isize).into(),
+                    line,
+                )
+            })
+            .collect()
+    }
+
+    /// Every grapheme of the label along with where it is drawn.
+    fn label_cells(&self) -> Vec<(Coordinates, &str)> {
+        self.lines()
+            .into_iter()
+            .flat_map(|(start, line)| {
+                text_area::lay_out(&self.label[line]).map(move |(x, g)| (start + (x, 0).into(), g))
+            })
+            .collect()
+    }
+
+    /// Where the caret of a text box goes when before the byte `offset` of its label.
+    pub fn caret_position(&self, offset: usize) -> Coordinates {
+        let lines = self.lines();
+        let (start, line) = lines
+            .iter()
+            .rev()
+            .find(|(_, line)| line.start <= offset)
+            .unwrap_or(&lines[0]);
+
+        *start
+            + (
+                text_area::columns(&self.label[line.start..offset.min(line.end)]) as isize,
+                0,
+            )
+                .into()
+    }
+
+    /// Every cell the shape is drawn over.
+    fn footprint(&self) -> Vec<Coordinates> {
+        let top_left = self.bounds.top_left;
+        let bottom_right = self.bounds.bottom_right();
+        let inside = self.inside();
+
+        let mut cells = (top_left.y..=bottom_right.y)
+            .flat_map(|y| (top_left.x..=bottom_right.x).map(move |x| (x, y).into()))
+            .filter(|c| !inside.contains(c))
+            .collect::<Vec<Coordinates>>();
+
+        cells.extend(self.label_cells().into_iter().flat_map(|(position, g)| {
+            (0..text_area::grapheme_width(g) as isize).map(move |i| position + (i, 0).into())
+        }));
+
+        cells
+    }
+
+    /// The side facing `position`, the top or bottom one on corners.
+    pub fn side_towards(&self, position: Coordinates) -> Direction {
+        let top_left = self.bounds.top_left;
+        let bottom_right = self.bounds.bottom_right();
+
+        if position.y <= top_left.y {
+            return Direction::Up;
+        } else if position.y >= bottom_right.y {
+            return Direction::Down;
+        } else if position.x <= top_left.x {
+            return Direction::Left;
+        } else if position.x >= bottom_right.x {
+            return Direction::Right;
+        }
+
+        // Inside of the shape, compare the distances to the sides relatively to its size
+        let center = top_left + bottom_right;
+        let (dx, dy) = (
+            (position.x * 2 - center.x) * self.bounds.height as isize,
+            (position.y * 2 - center.y) * self.bounds.width as isize,
+        );
+
+        match (dx.abs() > dy.abs(), dx > 0, dy > 0) {
+            (true, true, _) => Direction::Right,
+            (true, false, _) => Direction::Left,
+            (false, _, true) => Direction::Down,
+            (false, _, false) => Direction::Up,
+        }
+    }
+
+    /// Moves the side facing `direction` by one cell, outwards if `grow` is set and inwards
+    /// otherwise, the shape never getting smaller than its border.
+    pub fn resize(&mut self, direction: Direction, grow: bool) {
+        let bounds = &mut self.bounds;
+
+        match (direction, grow) {
+            (Direction::Right, true) => bounds.width += 1,
+            (Direction::Down, true) => bounds.height += 1,
+            (Direction::Left, true) => {
+                bounds.top_left.x -= 1;
+                bounds.width += 1;
+            }
+            (Direction::Up, true) => {
+                bounds.top_left.y -= 1;
+                bounds.height += 1;
+            }
+            (Direction::Right, false) if bounds.width > MIN_SIZE => bounds.width -= 1,
+            (Direction::Down, false) if bounds.height > MIN_SIZE => bounds.height -= 1,
+            (Direction::Left, false) if bounds.width > MIN_SIZE => {
+                bounds.top_left.x += 1;
+                bounds.width -= 1;
+            }
+            (Direction::Up, false) if bounds.height > MIN_SIZE => {
+                bounds.top_left.y += 1;
+                bounds.height -= 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where a link ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Cell(Coordinates),
+    /// The side of a shape facing the direction.
+    Side(usize, Direction),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Cell(position) => write!(f, "{},{}", position.x, position.y),
+            Endpoint::Side(id, side) => write!(f, "#{id}:{side}"),
+        }
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('#') {
+            Some(side) => {
+                let (id, side) = side.split_once(':').ok_or(())?;
+                Ok(Endpoint::Side(
+                    id.parse().map_err(|_| ())?,
+                    side.parse().map_err(|_| ())?,
+                ))
+            }
+            None => {
+                let (x, y) = s.split_once(',').ok_or(())?;
+                Ok(Endpoint::Cell(
+                    (x.parse().map_err(|_| ())?, y.parse().map_err(|_| ())?).into(),
+                ))
+            }
+        }
+    }
+}
+
+/// A connector with at least one end bound to a shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub from: Endpoint,
+    pub to: Endpoint,
+    pub style: LineStyle,
+    pub arrowhead: Arrowhead,
+    /// Shown next to the middle of the path.
+    pub label: String,
+    /// The cells the link was last drawn over, empty if it couldn't be routed.
+    pub path: Vec<Coordinates>,
+}
+
+impl Link {
+    /// Every grapheme of the label along with where it is drawn, by the middle of the longest
+    /// straight run of the path: beside it if the run is vertical and above it otherwise, clear
+    /// of the bends at either end when it fits.
+    fn label_cells(&self) -> Vec<(Coordinates, &str)> {
+        if self.label.is_empty() || self.path.len() < 2 {
+            return Vec::new();
+        }
+
+        // The runs as the indices of their first and last cells, joined at the bends
+        let mut runs = vec![(0, 1)];
+
+        for i in 2..self.path.len() {
+            let (first, last) = runs.last_mut().unwrap();
+            let vertical = |a: Coordinates, b: Coordinates| a.x == b.x;
+
+            if vertical(self.path[*first], self.path[*last])
+                == vertical(self.path[i - 1], self.path[i])
+            {
+                *last = i;
+            } else {
+                runs.push((i - 1, i));
+            }
+        }
+
+        // Labels only go above the horizontal runs they fit over, between their bends
+        let fits = |(first, last): &(usize, usize)| {
+            self.path[*first].x == self.path[*last].x
+                || last - first > text_area::columns(&self.label)
+        };
+        let (first, last) = match runs.iter().filter(|r| fits(r)).max_by_key(|(f, l)| l - f) {
+            Some(run) => *run,
+            None => runs.into_iter().max_by_key(|(f, l)| l - f).unwrap(),
+        };
+        let (a, b) = (self.path[first], self.path[last]);
+        let start: Coordinates = if a.x == b.x {
+            (a.x + 1, (a.y + b.y) / 2).into()
+        } else {
+            (a.x.min(b.x) + 1, a.y - 1).into()
+        };
+
+        text_area::lay_out(&self.label)
+            .map(|(x, g)| (start + (x, 0).into(), g))
+            .collect()
+    }
+
+    /// Every cell the link is drawn over.
+    fn footprint(&self) -> Vec<Coordinates> {
+        let mut cells = self.path.clone();
+
+        cells.extend(self.label_cells().into_iter().flat_map(|(position, g)| {
+            (0..text_area::grapheme_width(g) as isize).map(move |i| position + (i, 0).into())
+        }));
+
+        cells
+    }
+}
+
+/// An object holding text typed in Text mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextTarget {
+    /// The label of a text box.
+    Shape(usize),
+    Cell {
+        table: usize,
+        row: usize,
+        column: usize,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Objects {
+    pub shapes: BTreeMap<usize, Shape>,
+    pub links: Vec<Link>,
+    pub tables: BTreeMap<usize, Table>,
+    pub sequences: BTreeMap<usize, Sequence>,
+}
+
+impl Objects {
+    /// The identifier a new shape gets.
+    pub fn next_id(&self) -> usize {
+        self.shapes.keys().next_back().map_or(0, |id| id + 1)
+    }
+
+    /// The identifier a new table gets.
+    pub fn next_table_id(&self) -> usize {
+        self.tables.keys().next_back().map_or(0, |id| id + 1)
+    }
+
+    /// The table covering `position`, the most recent one if several do.
+    pub fn table_at(&self, position: Coordinates) -> Option<usize> {
+        self.tables
+            .iter()
+            .rev()
+            .find(|(_, table)| table.bounds().contains(&position))
+            .map(|(id, _)| *id)
+    }
+
+    /// The identifier a new sequence diagram gets.
+    pub fn next_sequence_id(&self) -> usize {
+        self.sequences.keys().next_back().map_or(0, |id| id + 1)
+    }
+
+    /// The sequence diagram covering `position`, the most recent one if several do.
+    pub fn sequence_at(&self, position: Coordinates) -> Option<usize> {
+        self.sequences
+            .iter()
+            .rev()
+            .find(|(_, sequence)| sequence.bounds().contains(&position))
+            .map(|(id, _)| *id)
+    }
+
+    /// The text box or table cell at `position`, if any.
+    pub fn text_target_at(&self, position: Coordinates) -> Option<TextTarget> {
+        if let Some(id) = self.table_at(position) {
+            let (row, column) = self.tables[&id].cell_at(position)?;
+
+            return Some(TextTarget::Cell {
+                table: id,
+                row,
+                column,
+            });
+        }
+
+        self.shape_at(position)
+            .filter(|id| self.shapes[id].text.is_some())
+            .map(TextTarget::Shape)
+    }
+
+    pub fn text(&self, target: TextTarget) -> Option<&String> {
+        match target {
+            TextTarget::Shape(id) => self.shapes.get(&id).map(|s| &s.label),
+            TextTarget::Cell { table, row, column } => self
+                .tables
+                .get(&table)
+                .and_then(|t| t.cells.get(row))
+                .and_then(|r| r.get(column)),
+        }
+    }
+
+    pub fn text_mut(&mut self, target: TextTarget) -> Option<&mut String> {
+        match target {
+            TextTarget::Shape(id) => self.shapes.get_mut(&id).map(|s| &mut s.label),
+            TextTarget::Cell { table, row, column } => self
+                .tables
+                .get_mut(&table)
+                .and_then(|t| t.cells.get_mut(row))
+                .and_then(|r| r.get_mut(column)),
+        }
+    }
+
+    /// Where the caret goes when before the byte `offset` of the text of `target`.
+    pub fn caret_position(&self, target: TextTarget, offset: usize) -> Option<Coordinates> {
+        match target {
+            TextTarget::Shape(id) => self.shapes.get(&id).map(|s| s.caret_position(offset)),
+            TextTarget::Cell { table, row, column } => {
+                let text = self.text(target)?;
+                let origin = self.tables[&table].cell_origin(row, column);
+                let x = text_area::columns(&text[..offset.min(text.len())]);
+
+                Some(origin + (x as isize, 0).into())
+            }
+        }
+    }
+
+    /// The shape covering `position`, the most recent one if several do.
+    pub fn shape_at(&self, position: Coordinates) -> Option<usize> {
+        self.shapes
+            .iter()
+            .rev()
+            .find(|(_, shape)| shape.bounds.contains(&position))
+            .map(|(id, _)| *id)
+    }
+
+    /// Removes a shape along with the links bound to it.
+    pub fn remove_shape(&mut self, id: usize) {
+        self.shapes.remove(&id);
+        self.links.retain(|link| {
+            ![link.from, link.to]
+                .iter()
+                .any(|e| matches!(e, Endpoint::Side(shape, _) if *shape == id))
+        });
+    }
+
+    /// The cell each bound end of the links is drawn at, keyed by the link index and whether it
+    /// is the `to` end. The ends bound to the same side are spread evenly along it, in the order
+    /// of where the other ends of their links are, so that the links don't cross right away.
+    fn attachments(&self) -> BTreeMap<(usize, bool), Coordinates> {
+        // Roughly where an end is, the center of the shape it is bound to
+        let center = |endpoint| match endpoint {
+            Endpoint::Cell(position) => Some(position),
+            Endpoint::Side(id, _) => self.shapes.get(&id).map(|shape| {
+                shape.bounds.top_left
+                    + (
+                        (shape.bounds.width / 2) as isize,
+                        (shape.bounds.height / 2) as isize,
+                    )
+                        .into()
+            }),
+        };
+
+        let mut sides = BTreeMap::<(usize, u8), Vec<(usize, bool)>>::new();
+
+        for (i, link) in self.links.iter().enumerate() {
+            for (end, endpoint) in [(false, link.from), (true, link.to)] {
+                if let Endpoint::Side(id, side) = endpoint {
+                    sides.entry((id, side as u8)).or_default().push((i, end));
+                }
+            }
+        }
+
+        let mut attachments = BTreeMap::new();
+
+        for ((id, side), mut ends) in sides {
+            let Some(shape) = self.shapes.get(&id) else {
+                continue;
+            };
+            let side = Direction::ALL[side as usize];
+
+            ends.sort_by_key(|(i, end)| {
+                let link = &self.links[*i];
+                let other = center(if *end { link.from } else { link.to });
+
+                other.map(|p| match side {
+                    Direction::Up | Direction::Down => p.x,
+                    Direction::Left | Direction::Right => p.y,
+                })
+            });
+            let top_left = shape.bounds.top_left;
+            let bottom_right = shape.bounds.bottom_right();
+
+            let (start, length) = match side {
+                Direction::Up | Direction::Down => (top_left.x, shape.bounds.width),
+                Direction::Left | Direction::Right => (top_left.y, shape.bounds.height),
+            };
+
+            for (n, end) in ends.iter().enumerate() {
+                let offset = start + (length * (n + 1) / (ends.len() + 1)) as isize;
+
+                attachments.insert(
+                    *end,
+                    match side {
+                        Direction::Up => (offset, top_left.y),
+                        Direction::Down => (offset, bottom_right.y),
+                        Direction::Left => (top_left.x, offset),
+                        Direction::Right => (bottom_right.x, offset),
+                    }
+                    .into(),
+                );
+            }
+        }
+
+        attachments
+    }
+}
+
+/// Replaces the object layer of `area` with `objects`, redrawing every object over the cells the
+/// previous ones were drawn on, as a single undo step. Links that can't be routed anymore are
+/// left undrawn until they can.
+pub fn update(area: &mut TextArea, mut objects: Objects) {
+    area.begin_undo_group();
+
+    let previous = area.objects().clone();
+
+    for cell in previous
+        .shapes
+        .values()
+        .flat_map(Shape::footprint)
+        .chain(previous.links.iter().flat_map(Link::footprint))
+        .chain(previous.tables.values().flat_map(|t| t.bounds().cells()))
+        .chain(previous.sequences.values().flat_map(|s| s.bounds().cells()))
+    {
+        area.erase_at(cell);
+    }
+
+    for table in objects.tables.values() {
+        table.draw(area);
+    }
+
+    for sequence in objects.sequences.values() {
+        sequence.draw(area);
+    }
+
+    for shape in objects.shapes.values_mut() {
+        shape.fit();
+        draw::rectangle(area, shape.bounds, shape.style, RectangleFill::Outline);
+
+        for (position, grapheme) in shape.label_cells() {
+            area.write_grapheme_at(position, grapheme);
+        }
+    }
+
+    let attachments = objects.attachments();
+    let insides = objects
+        .shapes
+        .values()
+        .map(Shape::inside)
+        .chain(objects.tables.values().map(Table::bounds))
+        .chain(objects.sequences.values().map(Sequence::bounds))
+        .collect::<Vec<_>>();
+
+    for (i, link) in objects.links.iter_mut().enumerate() {
+        let end = |endpoint, to| match endpoint {
+            Endpoint::Cell(position) => Some(position),
+            Endpoint::Side(..) => attachments.get(&(i, to)).copied(),
+        };
+
+        link.path = match (end(link.from, false), end(link.to, true)) {
+            (Some(from), Some(to)) => {
+                draw::connector(area, from, to, link.style, link.arrowhead, &insides)
+                    .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+
+        for (position, grapheme) in link.label_cells() {
+            area.write_grapheme_at(position, grapheme);
+        }
+    }
+
+    area.set_objects(objects);
+    area.end_undo_group();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_text() {
+        for (text, width, lines) in [
+            ("", 5, &[""][..]),
+            ("hello world", 11, &["hello world"]),
+            ("hello world", 5, &["hello", "world"]),
+            ("hello world", 8, &["hello", "world"]),
+            ("a b c d", 3, &["a b", "c d"]),
+            ("abcdefgh", 3, &["abc", "def", "gh"]),
+            ("one\ntwo three", 7, &["one", "two", "three"]),
+            ("a\n\nb", 4, &["a", "", "b"]),
+            ("世界世界", 5, &["世界", "世界"]),
+            ("ab", 0, &["a", "b"]),
+        ] {
+            let wrapped = wrap(text, width)
+                .into_iter()
+                .map(|range| &text[range])
+                .collect::<Vec<_>>();
+
+            assert_eq!(wrapped, lines, "{text:?} in {width} columns");
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! The character palette, a searchable grid of the characters useful for drawing, grouped by
+//! Unicode block along with the favourite and recently used ones.
+
+use lazy_static::lazy_static;
+use unicode_blocks::UnicodeBlock;
+use unicode_width::UnicodeWidthChar;
+
+use crate::text_area::Direction;
+
+/// How many recently used characters are remembered.
+pub const RECENT_LIMIT: usize = 32;
+
+/// The blocks shown in the palette, in order.
+const BLOCKS: [UnicodeBlock; 19] = [
+    unicode_blocks::BOX_DRAWING,
+    unicode_blocks::BLOCK_ELEMENTS,
+    unicode_blocks::GEOMETRIC_SHAPES,
+    unicode_blocks::ARROWS,
+    unicode_blocks::SUPPLEMENTAL_ARROWS_A,
+    unicode_blocks::SUPPLEMENTAL_ARROWS_B,
+    unicode_blocks::MISCELLANEOUS_SYMBOLS_AND_ARROWS,
+    unicode_blocks::MISCELLANEOUS_TECHNICAL,
+    unicode_blocks::MATHEMATICAL_OPERATORS,
+    unicode_blocks::MISCELLANEOUS_SYMBOLS,
+    unicode_blocks::DINGBATS,
+    unicode_blocks::BRAILLE_PATTERNS,
+    unicode_blocks::SYMBOLS_FOR_LEGACY_COMPUTING,
+    unicode_blocks::GEOMETRIC_SHAPES_EXTENDED,
+    unicode_blocks::ENCLOSED_ALPHANUMERICS,
+    unicode_blocks::CONTROL_PICTURES,
+    unicode_blocks::GENERAL_PUNCTUATION,
+    unicode_blocks::LATIN_1_SUPPLEMENT,
+    unicode_blocks::GREEK_AND_COPTIC,
+];
+
+lazy_static! {
+    /// The characters of every block that take one or two columns, with their lowercase names.
+    static ref CHARACTERS: Vec<(&'static str, Vec<(char, String)>)> = BLOCKS
+        .iter()
+        .map(|block| {
+            let chars = (block.start()..=block.end())
+                .filter_map(char::from_u32)
+                .filter(|c| matches!(c.width(), Some(1 | 2)))
+                .filter_map(|c| Some((c, name(c)?.to_lowercase())))
+                .collect();
+
+            (block.name(), chars)
+        })
+        .collect();
+}
+
+/// The Unicode name of `c`, if it has one.
+pub fn name(c: char) -> Option<String> {
+    unicode_names2::name(c).map(|n| n.to_string())
+}
+
+/// Whether `c`, called `name` in lowercase, is found by `query`: every word of the query being in
+/// the name, or the query being the character itself or its code point, like `u+2500`.
+fn matches(c: char, name: &str, query: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    let code = query
+        .strip_prefix("u+")
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+
+    query.split_whitespace().all(|word| name.contains(word))
+        || query
+            .chars()
+            .eq([c].into_iter().flat_map(char::to_lowercase))
+        || code == Some(c as u32)
+}
+
+/// A titled run of characters of the palette.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub chars: Vec<char>,
+}
+
+/// A row of the palette laid out a given number of characters wide.
+#[derive(Debug, Clone, Copy)]
+pub enum Row<'a> {
+    Title(&'a str),
+    /// Characters of a group along with the index of the first one among every character shown.
+    Chars(usize, &'a [char]),
+}
+
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub query: String,
+    /// The groups holding characters found by the query, empty groups being left out.
+    pub groups: Vec<Group>,
+    /// The index of the selected character among every character shown.
+    pub selected: usize,
+    /// The first row shown.
+    pub top: usize,
+}
+
+impl Palette {
+    pub fn new(query: &str, recent: &[char], favourites: &[char]) -> Self {
+        let mut palette = Self {
+            query: query.to_string(),
+            groups: Vec::new(),
+            selected: 0,
+            top: 0,
+        };
+
+        palette.update(recent, favourites);
+        palette
+    }
+
+    /// Looks for the characters found by the query again, keeping the selected character if it
+    /// still is.
+    pub fn update(&mut self, recent: &[char], favourites: &[char]) {
+        let selected = self.selected();
+        let found = |chars: &[char]| {
+            chars
+                .iter()
+                .copied()
+                .filter(|c| {
+                    let name = name(*c).unwrap_or_default().to_lowercase();
+                    matches(*c, &name, &self.query)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut groups = vec![
+            Group {
+                name: "Favourites".to_string(),
+                chars: found(favourites),
+            },
+            Group {
+                name: "Recently used".to_string(),
+                chars: found(recent),
+            },
+        ];
+
+        groups.extend(CHARACTERS.iter().map(|(name, chars)| {
+            Group {
+                name: name.to_string(),
+                chars: chars
+                    .iter()
+                    .filter(|(c, name)| matches(*c, name, &self.query))
+                    .map(|(c, _)| *c)
+                    .collect(),
+            }
+        }));
+
+        groups.retain(|g| !g.chars.is_empty());
+        self.groups = groups;
+
+        self.selected = selected
+            .and_then(|s| self.chars().position(|c| c == s))
+            .unwrap_or(0);
+    }
+
+    /// Every character shown, in order.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.groups.iter().flat_map(|g| g.chars.iter().copied())
+    }
+
+    pub fn selected(&self) -> Option<char> {
+        self.chars().nth(self.selected)
+    }
+
+    /// The rows of the palette, each group being its title followed by its characters, `width`
+    /// characters per row.
+    pub fn rows(&self, width: usize) -> Vec<Row<'_>> {
+        let mut rows = Vec::new();
+        let mut index = 0;
+
+        for group in &self.groups {
+            rows.push(Row::Title(&group.name));
+
+            for chars in group.chars.chunks(width.max(1)) {
+                rows.push(Row::Chars(index, chars));
+                index += chars.len();
+            }
+        }
+
+        rows
+    }
+
+    /// Scrolls the rows, `width` characters wide, just enough for the selected character to be
+    /// within the `height` rows shown, along with the title of its group if there is room.
+    /// Returns the first row shown.
+    pub fn scroll(&mut self, width: usize, height: usize) -> usize {
+        let rows = self.rows(width);
+        let selected = rows.iter().position(|row| {
+            matches!(row, Row::Chars(first, chars) if (*first..first + chars.len()).contains(&self.selected))
+        });
+
+        if let Some(row) = selected {
+            if row <= self.top {
+                self.top = row.saturating_sub(1);
+            } else if row >= self.top + height {
+                self.top = row + 1 - height.max(1);
+            }
+        }
+
+        self.top
+    }
+
+    /// Moves the selection to the neighbouring character in `direction`, the rows being `width`
+    /// characters wide. Moving up or down goes to the same column of the next row of characters,
+    /// or to its last character if it is shorter.
+    pub fn move_selection(&mut self, direction: Direction, width: usize) {
+        let count = self.chars().count();
+
+        let rows = self
+            .rows(width)
+            .into_iter()
+            .filter_map(|row| match row {
+                Row::Chars(first, chars) => Some((first, chars.len())),
+                Row::Title(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let Some(row) = rows
+            .iter()
+            .position(|(first, len)| (*first..first + len).contains(&self.selected))
+        else {
+            return;
+        };
+        let column = self.selected - rows[row].0;
+
+        let vertical =
+            |row: Option<&(usize, usize)>| row.map(|(first, len)| first + column.min(len - 1));
+
+        let target = match direction {
+            Direction::Left => self.selected.checked_sub(1),
+            Direction::Right => Some(self.selected + 1).filter(|s| *s < count),
+            Direction::Up => vertical(row.checked_sub(1).map(|r| &rows[r])),
+            Direction::Down => vertical(rows.get(row + 1)),
+        };
+
+        if let Some(target) = target {
+            self.selected = target;
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Orthogonal routing of connectors around what is already drawn on the grid.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::text_area::{BoundingBox, Coordinates, Direction, TextArea};
+
+/// How far a route may stray outside of the box spanning its ends, in cells.
+const MARGIN: isize = 16;
+
+/// The extra cost of a bend, so that among routes of the same length the straightest wins.
+const BEND_COST: usize = 2;
+
+/// A cell of a route and the direction it was entered from, if any.
+type State = (Coordinates, Option<Direction>);
+
+/// The cheapest orthogonal path from `start` to `end`, both included, only going through blank
+/// cells outside of `avoid` in between. Found with A*, the length of a path plus its bends being
+/// its cost.
+pub fn route(
+    area: &TextArea,
+    start: Coordinates,
+    end: Coordinates,
+    avoid: &[BoundingBox],
+) -> Option<Vec<Coordinates>> {
+    let spanning = BoundingBox::spanning(start, end);
+    let bounds = BoundingBox::new(
+        spanning.top_left - (MARGIN, MARGIN).into(),
+        spanning.width + 2 * MARGIN as usize,
+        spanning.height + 2 * MARGIN as usize,
+    );
+    let estimate = |p: Coordinates| p.x.abs_diff(end.x) + p.y.abs_diff(end.y);
+
+    let mut costs = HashMap::<State, usize>::from([((start, None), 0)]);
+    let mut previous = HashMap::<State, State>::new();
+    // Coordinates and directions aren't ordered, so they go in the queue as plain numbers.
+    let mut queue = BinaryHeap::from([Reverse((estimate(start), 0, (start.x, start.y), None))]);
+
+    while let Some(Reverse((_, cost, (x, y), heading))) = queue.pop() {
+        let position = Coordinates::from((x, y));
+        let heading = heading.map(|i: u8| Direction::ALL[i as usize]);
+
+        if position == end {
+            let mut state = (position, heading);
+            let mut path = vec![position];
+
+            while let Some(p) = previous.get(&state) {
+                path.push(p.0);
+                state = *p;
+            }
+
+            path.reverse();
+            return Some(path);
+        }
+
+        if costs.get(&(position, heading)).is_some_and(|c| *c < cost) {
+            continue;
+        }
+
+        for direction in Direction::ALL {
+            let next = position + direction.vector();
+
+            if heading == Some(direction.opposite())
+                || !bounds.contains(&next)
+                || (next != end
+                    && (!area.is_blank(&next) || avoid.iter().any(|b| b.contains(&next))))
+            {
+                continue;
+            }
+
+            let cost = cost
+                + 1
+                + match heading {
+                    Some(heading) if heading != direction => BEND_COST,
+                    _ => 0,
+                };
+            let state = (next, Some(direction));
+
+            if costs.get(&state).is_none_or(|c| cost < *c) {
+                costs.insert(state, cost);
+                previous.insert(state, (position, heading));
+                queue.push(Reverse((
+                    cost + estimate(next),
+                    cost,
+                    (next.x, next.y),
+                    Some(direction as u8),
+                )));
+            }
+        }
+    }
+
+    None
+}
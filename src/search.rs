@@ -0,0 +1,119 @@
+//! Search for text along the rows of the grid, and optionally along its columns.
+
+use std::collections::HashSet;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::text_area::{self, BoundingBox, Cell, Coordinates, TextArea};
+
+/// The cells of every match of `pattern` in the runs of touching characters of the grid, from
+/// left to right along rows and, with `columns`, from top to bottom along columns as well. A
+/// match along a column is one cell wide, and only patterns of several characters are looked
+/// for there, as the others are found along rows already. The matches are ordered from top to
+/// bottom and left to right.
+pub fn find(area: &TextArea, pattern: &str, columns: bool) -> Vec<BoundingBox> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let mut found = Vec::new();
+    let length = pattern.graphemes(true).count();
+    let starts = area
+        .graphemes()
+        .map(|(position, _)| position)
+        .collect::<Vec<_>>();
+
+    for &start in &starts {
+        if area.cell_at(start - (1, 0).into()).is_none() {
+            let run = area.string_at(start);
+
+            found.extend(run.match_indices(pattern).map(|(i, _)| {
+                let x = start.x + text_area::columns(&run[..i]) as isize;
+                BoundingBox::new((x, start.y), text_area::columns(pattern), 1)
+            }));
+        }
+
+        if columns && length > 1 && area.cell_at(start - (0, 1).into()).is_none() {
+            let mut run = String::new();
+            let mut position = start;
+
+            while let Some(Cell::Grapheme(g)) = area.cell_at(position) {
+                run.push_str(g);
+                position += (0, 1).into();
+            }
+
+            found.extend(run.match_indices(pattern).map(|(i, _)| {
+                let y = start.y + run[..i].graphemes(true).count() as isize;
+                BoundingBox::new((start.x, y), 1, length)
+            }));
+        }
+    }
+
+    found.sort_by_key(|b| (b.top_left.y, b.top_left.x));
+    found
+}
+
+/// The first match after `position`, or before it going backwards, wrapping around the ends.
+pub fn next(matches: &[BoundingBox], position: Coordinates, forward: bool) -> Option<Coordinates> {
+    let key = |p: &Coordinates| (p.y, p.x);
+    let starts = matches.iter().map(|b| b.top_left);
+
+    if forward {
+        starts
+            .clone()
+            .find(|p| key(p) > key(&position))
+            .or(starts.clone().next())
+    } else {
+        starts
+            .clone()
+            .rev()
+            .find(|p| key(p) < key(&position))
+            .or(starts.clone().next_back())
+    }
+}
+
+/// Replaces every match of `pattern` within `region`, or in the whole grid, with `replacement`,
+/// written the way the match goes, as a single undo step. What follows a match is left where it
+/// is, a longer replacement covering it, along with the matches it covers. Returns how many
+/// matches were replaced.
+pub fn replace(
+    area: &mut TextArea,
+    pattern: &str,
+    replacement: &str,
+    columns: bool,
+    region: Option<BoundingBox>,
+) -> usize {
+    let matches = find(area, pattern, columns).into_iter().filter(|b| {
+        region.is_none_or(|r| r.contains(&b.top_left) && r.contains(&b.bottom_right()))
+    });
+    let mut written = HashSet::new();
+    let mut count = 0;
+
+    area.begin_undo_group();
+
+    for bounds in matches {
+        if bounds.cells().any(|position| written.contains(&position)) {
+            continue;
+        }
+
+        for position in bounds.cells() {
+            area.erase_at(position);
+        }
+
+        for (row, (x, grapheme)) in text_area::lay_out(replacement).enumerate() {
+            let position = match bounds.height > 1 {
+                true => bounds.top_left + (0, row as isize).into(),
+                false => bounds.top_left + (x, 0).into(),
+            };
+            let width = text_area::grapheme_width(grapheme) as isize;
+
+            area.write_grapheme_at(position, grapheme);
+            written.extend((0..width).map(|x| position + (x, 0).into()));
+        }
+
+        count += 1;
+    }
+
+    area.end_undo_group();
+    count
+}
@@ -0,0 +1,263 @@
+//! Sequence diagrams of the object layer, laid out from a small text language:
+//!
+//! ```text
+//! participant Client
+//! Client -> Server: request
+//! Server --> Client: reply
+//! ```
+//!
+//! Participants are boxed in the order they are declared or first appear in, with a lifeline
+//! going down from each. Every message is an arrow between two lifelines with its text above, `->`
+//! drawing a solid line and `-->` a dashed one. A participant can send a message to itself.
+
+use crate::{
+    draw,
+    glyphs::{self, Arrowhead, LineStyle, DOWN, LEFT, RIGHT, UP},
+    text_area::{self, BoundingBox, Coordinates, Direction, TextArea},
+};
+
+/// The blank columns between the boxes of two participants.
+const GAP: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Message {
+    from: usize,
+    to: usize,
+    text: String,
+    dashed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Diagram {
+    participants: Vec<String>,
+    messages: Vec<Message>,
+}
+
+/// Reads the source of a diagram, failing with the line number of the first invalid line.
+fn parse(source: &str) -> Result<Diagram, String> {
+    fn participant(diagram: &mut Diagram, name: &str) -> usize {
+        match diagram.participants.iter().position(|p| p == name) {
+            Some(i) => i,
+            None => {
+                diagram.participants.push(name.to_string());
+                diagram.participants.len() - 1
+            }
+        }
+    }
+
+    let mut diagram = Diagram::default();
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        let invalid = |message: &str| format!("line {}: {message}", i + 1);
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("participant ") {
+            participant(&mut diagram, name.trim());
+            continue;
+        }
+
+        // The text can hold arrows of its own
+        let (arrow, text) = line.split_once(':').unwrap_or((line, ""));
+        let (from, to, dashed) = match arrow.split_once("-->") {
+            Some((from, to)) => (from, to, true),
+            None => match arrow.split_once("->") {
+                Some((from, to)) => (from, to, false),
+                None => return Err(invalid("expected `from -> to: text`")),
+            },
+        };
+        let (from, to) = (from.trim(), to.trim());
+
+        if from.is_empty() || to.is_empty() {
+            return Err(invalid("missing participant"));
+        }
+
+        let message = Message {
+            from: participant(&mut diagram, from),
+            to: participant(&mut diagram, to),
+            text: text.trim().to_string(),
+            dashed,
+        };
+        diagram.messages.push(message);
+    }
+
+    if diagram.participants.is_empty() {
+        return Err("no participants".to_string());
+    }
+
+    Ok(diagram)
+}
+
+/// Checks the source of a diagram, returning the first error found in it.
+pub fn check(source: &str) -> Result<(), String> {
+    parse(source).map(|_| ())
+}
+
+/// The glyph of dashed lines in the given style.
+fn dashed(style: LineStyle) -> char {
+    match style {
+        LineStyle::Heavy => '┅',
+        LineStyle::Ascii => '.',
+        _ => '┄',
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    pub origin: Coordinates,
+    /// What the diagram is laid out from, which is valid as it was checked when set.
+    pub source: String,
+    pub style: LineStyle,
+    pub arrowhead: Arrowhead,
+}
+
+/// Where every part of a diagram goes, relative to its origin.
+struct Layout {
+    diagram: Diagram,
+    /// The column of the lifeline of every participant.
+    lifelines: Vec<usize>,
+    /// The width of the box of every participant.
+    widths: Vec<usize>,
+    width: usize,
+    height: usize,
+}
+
+impl Sequence {
+    fn layout(&self) -> Layout {
+        let diagram = parse(&self.source).unwrap_or_default();
+        let widths = diagram
+            .participants
+            .iter()
+            .map(|p| text_area::columns(p) + 4)
+            .collect::<Vec<_>>();
+
+        let mut lifelines = vec![widths[0] / 2];
+
+        for i in 1..widths.len() {
+            let previous_right = lifelines[i - 1] + (widths[i - 1] - 1 - widths[i - 1] / 2);
+            lifelines.push(previous_right + GAP + 1 + widths[i] / 2);
+        }
+
+        // Moving a lifeline moves all those after it, which only spreads the others further
+        let mut width = lifelines.last().unwrap() + widths.last().unwrap() / 2 + 1;
+
+        for message in &diagram.messages {
+            let text = text_area::columns(&message.text);
+            let (left, right, needed) = match message.from == message.to {
+                true => (message.from, message.from + 1, text + 6),
+                false => (
+                    message.from.min(message.to),
+                    message.from.max(message.to),
+                    text + 4,
+                ),
+            };
+
+            match lifelines.get(right) {
+                Some(x) if *x < lifelines[left] + needed => {
+                    let shift = lifelines[left] + needed - x;
+                    lifelines[right..].iter_mut().for_each(|x| *x += shift);
+                    width += shift;
+                }
+                Some(_) => {}
+                None => width = width.max(lifelines[left] + needed),
+            }
+        }
+
+        let height = 3 + 2 * diagram.messages.len() + 1;
+
+        Layout {
+            diagram,
+            lifelines,
+            widths,
+            width,
+            height,
+        }
+    }
+
+    pub fn bounds(&self) -> BoundingBox {
+        let layout = self.layout();
+        BoundingBox::new(self.origin, layout.width, layout.height)
+    }
+
+    pub fn draw(&self, area: &mut TextArea) {
+        let layout = self.layout();
+        let at = |x: usize, y: usize| self.origin + (x as isize, y as isize).into();
+        let bottom = layout.height - 1;
+
+        for (i, name) in layout.diagram.participants.iter().enumerate() {
+            let (x, width) = (layout.lifelines[i], layout.widths[i]);
+            let left = x - width / 2;
+
+            draw::rectangle(
+                area,
+                BoundingBox::new(at(left, 0), width, 3),
+                self.style,
+                draw::RectangleFill::Outline,
+            );
+            area.write_str_at(at(left + 2, 1), name);
+
+            // Junctions are given whole, as ASCII lines aren't joined with their neighbours
+            draw::connect(area, at(x, 2), LEFT | RIGHT | DOWN, self.style);
+
+            for y in 3..=bottom {
+                draw::connect(area, at(x, y), UP | DOWN, self.style);
+            }
+        }
+
+        for (n, message) in layout.diagram.messages.iter().enumerate() {
+            let y = 3 + 2 * n;
+            let from = layout.lifelines[message.from];
+            let line = |area: &mut TextArea, x: usize, y: usize| match message.dashed {
+                true => area.write_at(at(x, y), dashed(self.style)),
+                false => draw::connect(area, at(x, y), LEFT | RIGHT, self.style),
+            };
+            // Without an arrowhead, the line goes on to the lifeline
+            let head = |area: &mut TextArea, x: usize, y: usize, direction: Direction| match self
+                .arrowhead
+            {
+                Arrowhead::None => line(area, x, y),
+                arrowhead => area.write_at(at(x, y), arrowhead.glyph(direction)),
+            };
+
+            if message.from == message.to {
+                draw::connect(area, at(from, y), UP | RIGHT | DOWN, self.style);
+                line(area, from + 1, y);
+                draw::connect(area, at(from + 2, y), LEFT | DOWN, self.style);
+                area.write_str_at(at(from + 4, y), &message.text);
+
+                head(area, from + 1, y + 1, Direction::Left);
+                draw::connect(area, at(from + 2, y + 1), UP | LEFT, self.style);
+                continue;
+            }
+
+            let to = layout.lifelines[message.to];
+            let direction = if to > from {
+                Direction::Right
+            } else {
+                Direction::Left
+            };
+
+            area.write_str_at(at(from.min(to) + 2, y), &message.text);
+
+            let tee = UP | DOWN | glyphs::towards(direction);
+            draw::connect(area, at(from, y + 1), tee, self.style);
+
+            let (end, line_cells) = match direction {
+                Direction::Right => (to - 1, from + 1..to - 1),
+                _ => (to + 1, to + 2..from),
+            };
+
+            for x in line_cells {
+                // Lifelines crossed on the way are left whole
+                if !layout.lifelines.contains(&x) {
+                    line(area, x, y + 1);
+                }
+            }
+
+            head(area, end, y + 1, direction);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Tables of the object layer, drawn as a grid of cells whose columns fit their longest text.
+
+use crate::{
+    glyphs::{self, LineStyle},
+    text_area::{self, BoundingBox, Coordinates, TextArea},
+};
+
+/// The blank columns on each side of the text of a cell.
+const PADDING: usize = 1;
+
+/// A change of the rows or columns of a table, relative to the cell under the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableEdit {
+    /// Inserts a row below the cell.
+    InsertRow,
+    /// Inserts a column right of the cell.
+    InsertColumn,
+    DeleteRow,
+    DeleteColumn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub top_left: Coordinates,
+    /// The text of every cell, row by row, every row having as many cells.
+    pub cells: Vec<Vec<String>>,
+    pub style: LineStyle,
+}
+
+impl Table {
+    pub fn new(top_left: Coordinates, rows: usize, columns: usize, style: LineStyle) -> Self {
+        Self {
+            top_left,
+            cells: vec![vec![String::new(); columns.max(1)]; rows.max(1)],
+            style,
+        }
+    }
+
+    /// A table of the given rows, padded with empty cells to the length of the longest one.
+    pub fn from_rows(top_left: Coordinates, mut cells: Vec<Vec<String>>, style: LineStyle) -> Self {
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+        for row in &mut cells {
+            row.resize(columns, String::new());
+        }
+
+        if cells.is_empty() {
+            cells.push(vec![String::new(); columns]);
+        }
+
+        Self {
+            top_left,
+            cells,
+            style,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn columns(&self) -> usize {
+        self.cells[0].len()
+    }
+
+    /// The width of the text of every column, which is the one of its longest cell.
+    fn widths(&self) -> Vec<usize> {
+        (0..self.columns())
+            .map(|c| {
+                self.cells
+                    .iter()
+                    .map(|row| text_area::columns(&row[c]))
+                    .max()
+                    .unwrap_or(0)
+                    .max(1)
+            })
+            .collect()
+    }
+
+    /// The columns of the grid the vertical lines are drawn at, relative to the table.
+    fn rules(&self) -> Vec<usize> {
+        let mut rules = vec![0];
+
+        for width in self.widths() {
+            rules.push(rules.last().unwrap() + width + 2 * PADDING + 1);
+        }
+
+        rules
+    }
+
+    pub fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(
+            self.top_left,
+            self.rules().last().unwrap() + 1,
+            2 * self.rows() + 1,
+        )
+    }
+
+    /// The row and column of the cell at `position`, lines excluded.
+    pub fn cell_at(&self, position: Coordinates) -> Option<(usize, usize)> {
+        let relative = self.bounds().convert_to_relative(&position)?;
+        let (x, y) = (relative.x as usize, relative.y as usize);
+
+        if y % 2 == 0 {
+            return None;
+        }
+
+        let rules = self.rules();
+        let column = rules.windows(2).position(|r| r[0] < x && x < r[1])?;
+
+        Some((y / 2, column))
+    }
+
+    /// Where the text of a cell starts.
+    pub fn cell_origin(&self, row: usize, column: usize) -> Coordinates {
+        let x = self.rules()[column] + 1 + PADDING;
+
+        self.top_left + (x as isize, (2 * row + 1) as isize).into()
+    }
+
+    pub fn draw(&self, area: &mut TextArea) {
+        let bounds = self.bounds();
+        let rules = self.rules();
+        let (width, height) = (bounds.width - 1, bounds.height - 1);
+
+        for y in 0..=height {
+            for x in 0..=width {
+                let mut connections = 0;
+
+                if y % 2 == 0 {
+                    if x > 0 {
+                        connections |= glyphs::LEFT;
+                    }
+                    if x < width {
+                        connections |= glyphs::RIGHT;
+                    }
+                }
+
+                if rules.contains(&x) {
+                    if y > 0 {
+                        connections |= glyphs::UP;
+                    }
+                    if y < height {
+                        connections |= glyphs::DOWN;
+                    }
+                }
+
+                if connections != 0 {
+                    let position = self.top_left + (x as isize, y as isize).into();
+                    area.write_at(position, glyphs::glyph(connections, self.style));
+                }
+            }
+        }
+
+        for (r, row) in self.cells.iter().enumerate() {
+            for (c, text) in row.iter().enumerate() {
+                area.write_str_at(self.cell_origin(r, c), text);
+            }
+        }
+    }
+
+    /// Inserts an empty row before `row`.
+    pub fn insert_row(&mut self, row: usize) {
+        let columns = self.columns();
+        self.cells
+            .insert(row.min(self.rows()), vec![String::new(); columns]);
+    }
+
+    /// Inserts an empty column before `column`.
+    pub fn insert_column(&mut self, column: usize) {
+        for row in &mut self.cells {
+            row.insert(column.min(row.len()), String::new());
+        }
+    }
+
+    /// Removes a row, returning whether any is left.
+    pub fn remove_row(&mut self, row: usize) -> bool {
+        if row < self.rows() {
+            self.cells.remove(row);
+        }
+
+        !self.cells.is_empty()
+    }
+
+    /// Removes a column, returning whether any is left.
+    pub fn remove_column(&mut self, column: usize) -> bool {
+        if column < self.columns() {
+            for row in &mut self.cells {
+                row.remove(column);
+            }
+        }
+
+        self.columns() > 0
+    }
+}
+
+/// Reads comma or tab separated values, fields being optionally quoted with `"`, and `""` standing
+/// for a quote inside of those.
+pub fn parse_delimited(s: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(if c == '\n' { ' ' } else { c }),
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_delimited_values() {
+        for (s, delimiter, rows) in [
+            ("", ',', &[][..]),
+            ("a,b\nc,d\n", ',', &[&["a", "b"][..], &["c", "d"]]),
+            ("a,b\r\nc", ',', &[&["a", "b"], &["c"]]),
+            ("a,,\n\n", ',', &[&["a", "", ""], &[""]]),
+            ("a\tb,c", '\t', &[&["a", "b,c"]]),
+            ("\"a,b\",\"say \"\"hi\"\"\"", ',', &[&["a,b", "say \"hi\""]]),
+            ("\"two\nlines\",x", ',', &[&["two lines", "x"]]),
+            ("x\"y", ',', &[&["x\"y"]]),
+        ] {
+            assert_eq!(parse_delimited(s, delimiter), rows, "{s:?}");
+        }
+    }
+}
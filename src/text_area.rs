@@ -1,8 +1,15 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
+    fmt,
     ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign},
+    str::FromStr,
 };
 
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::objects::Objects;
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Coordinates {
     pub x: isize,
@@ -76,7 +83,7 @@ impl SubAssign<Coordinates> for Coordinates {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BoundingBox {
     pub top_left: Coordinates,
     pub width: usize,
@@ -95,6 +102,28 @@ impl BoundingBox {
         }
     }
 
+    /// The smallest bounding box holding both `a` and `b`.
+    pub fn spanning(a: Coordinates, b: Coordinates) -> Self {
+        Self::new(
+            (a.x.min(b.x), a.y.min(b.y)),
+            a.x.abs_diff(b.x) + 1,
+            a.y.abs_diff(b.y) + 1,
+        )
+    }
+
+    /// The bottom right cell of the box, which is inside of it.
+    pub fn bottom_right(&self) -> Coordinates {
+        self.top_left + (self.width as isize - 1, self.height as isize - 1).into()
+    }
+
+    /// Every cell of the box, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = Coordinates> {
+        let (top_left, width) = (self.top_left, self.width as isize);
+
+        (0..self.height as isize)
+            .flat_map(move |y| (0..width).map(move |x| top_left + (x, y).into()))
+    }
+
     pub fn contains(&self, point: &Coordinates) -> bool {
         (self.top_left.x..self.top_left.x.saturating_add_unsigned(self.width)).contains(&point.x)
             && (self.top_left.y..self.top_left.y.saturating_add_unsigned(self.height))
@@ -127,8 +156,51 @@ impl From<(isize, isize)> for Coordinates {
     }
 }
 
+/// The content of a cell of the grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cell {
+    /// A grapheme cluster: a base character followed by any combining marks.
+    Grapheme(String),
+    /// The right half of a double width grapheme, which starts in the cell to its left.
+    Continuation,
+}
+
+impl Cell {
+    /// How many columns the cell's grapheme spans, continuations spanning none of their own.
+    pub fn width(&self) -> usize {
+        match self {
+            Cell::Grapheme(g) => grapheme_width(g),
+            Cell::Continuation => 0,
+        }
+    }
+}
+
+/// How many columns `grapheme` spans on the grid: 2 for wide characters, 1 otherwise.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().clamp(1, 2)
+}
+
+/// How many columns typing `c` advances the cursor by, combining marks not advancing it at all.
+pub fn char_width(c: char) -> usize {
+    c.width().unwrap_or(1).min(2)
+}
+
+/// How many columns `s` spans when laid out on a single row.
+pub fn columns(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Lays `s` out on a single row, yielding every grapheme along with its column offset.
+pub fn lay_out(s: &str) -> impl Iterator<Item = (isize, &str)> {
+    s.graphemes(true).scan(0, |x, grapheme| {
+        let offset = *x;
+        *x += grapheme_width(grapheme) as isize;
+        Some((offset, grapheme))
+    })
+}
+
 struct TextStorage {
-    characters: HashMap<Coordinates, char>,
+    characters: HashMap<Coordinates, Cell>,
 }
 
 impl TextStorage {
@@ -138,6 +210,8 @@ impl TextStorage {
         }
     }
 
+    /// Every character in `bounding_box`, combining marks sharing the coordinates of the base
+    /// character they apply to.
     pub fn characters_in_bounding_box<B>(
         &self,
         bounding_box: B,
@@ -147,15 +221,19 @@ impl TextStorage {
     {
         let bounding_box = bounding_box.into();
 
-        self.characters.iter().filter_map(move |(coords, c)| {
-            bounding_box
-                .convert_to_relative(coords)
-                .map(|coords| (coords, *c))
-        })
+        self.characters
+            .iter()
+            .filter_map(move |(coords, cell)| match cell {
+                Cell::Grapheme(g) => bounding_box
+                    .convert_to_relative(coords)
+                    .map(|coords| g.chars().map(move |c| (coords, c))),
+                Cell::Continuation => None,
+            })
+            .flatten()
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Direction {
     Up,
@@ -164,7 +242,31 @@ pub enum Direction {
     Left,
 }
 
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Direction::ALL
+            .into_iter()
+            .find(|direction| direction.to_string() == s)
+            .ok_or(())
+    }
+}
+
 impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
     pub fn vector(&self) -> Coordinates {
         match self {
             Direction::Up => (0, -1),
@@ -174,16 +276,34 @@ impl Direction {
         }
         .into()
     }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+        }
+    }
 }
 
 enum Change {
-    AddedChar(Coordinates, char, char),
-    RemovedChar(Coordinates, char),
+    AddedCell(Coordinates, Cell, Option<Cell>),
+    RemovedCell(Coordinates, Cell),
+    /// The object layer was replaced, from the first state to the second.
+    ReplacedObjects(Box<Objects>, Box<Objects>),
 }
 
+/// The undo history, where every entry is a group of changes undone and redone together.
 struct History {
-    changes: Vec<Change>,
+    changes: Vec<Vec<Change>>,
     cursor: usize,
+    /// How many groups are currently open, changes being merged into a single entry while any is.
+    group_depth: usize,
+    /// Whether the entry for the currently open group has been created yet.
+    group_started: bool,
+    /// The cursor when the area was last saved, unless that state can't be reached anymore.
+    saved: Option<usize>,
 }
 
 impl History {
@@ -191,40 +311,80 @@ impl History {
         Self {
             changes: Vec::new(),
             cursor: 0,
+            group_depth: 0,
+            group_started: false,
+            saved: Some(0),
         }
     }
 
-    pub fn undo(&mut self) -> Option<&Change> {
+    pub fn is_modified(&self) -> bool {
+        self.saved != Some(self.cursor)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.cursor);
+    }
+
+    pub fn mark_modified(&mut self) {
+        self.saved = None;
+    }
+
+    pub fn undo(&mut self) -> Option<&[Change]> {
         if self.cursor == 0 {
             None
         } else {
             self.cursor -= 1;
-            self.changes.get(self.cursor)
+            self.changes.get(self.cursor).map(|c| c.as_slice())
         }
     }
 
-    pub fn redo(&mut self) -> Option<&Change> {
+    pub fn redo(&mut self) -> Option<&[Change]> {
         if self.changes.len() == self.cursor {
             None
         } else {
-            let ret = self.changes.get(self.cursor);
+            let ret = self.changes.get(self.cursor).map(|c| c.as_slice());
             self.cursor += 1;
             ret
         }
     }
 
     pub fn add(&mut self, change: Change) {
+        if self.group_depth > 0 && self.group_started {
+            self.changes[self.cursor - 1].push(change);
+            if self.saved == Some(self.cursor) {
+                self.saved = None;
+            }
+            return;
+        }
+
         if self.changes.len() != self.cursor {
             self.changes.truncate(self.cursor);
+            if self.saved.is_some_and(|saved| saved > self.cursor) {
+                self.saved = None;
+            }
         }
 
-        self.changes.push(change);
+        self.changes.push(vec![change]);
         self.cursor += 1;
+        self.group_started = self.group_depth > 0;
+    }
+
+    pub fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.group_started = false;
+        }
+
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
     }
 }
 
 pub struct TextArea {
     text_storage: TextStorage,
+    objects: Objects,
     pub bounding_box: BoundingBox,
     cursor_absolute_position: Coordinates,
     view_cache: Option<Vec<(Coordinates, char)>>,
@@ -235,6 +395,7 @@ impl TextArea {
     pub fn new(width: usize, height: usize) -> Self {
         Self {
             text_storage: TextStorage::new(),
+            objects: Objects::default(),
             bounding_box: (Coordinates::from((0, 0)), width, height).into(),
             cursor_absolute_position: (0, 0).into(),
             view_cache: None,
@@ -294,128 +455,451 @@ impl TextArea {
         }
     }
 
-    pub fn move_cursor(&mut self, direction: Direction) {
-        self.cursor_absolute_position += direction.vector();
+    pub fn move_cursor_to(&mut self, position: Coordinates) {
+        self.cursor_absolute_position = position;
+        self.snap_cursor(Direction::Left);
         self.adjust_view_to_cursor();
         self.view_cache = None;
     }
 
     pub fn move_cursor_by(&mut self, direction: Direction, amount: usize) {
         self.cursor_absolute_position += direction.vector() * amount as isize;
+        self.snap_cursor(direction);
         self.adjust_view_to_cursor();
         self.view_cache = None;
     }
 
-    pub fn write_at_cursor(&mut self, c: char) {
-        let mut old = None;
-        self.text_storage
-            .characters
-            .entry(self.cursor_absolute_position)
-            .and_modify(|x| {
-                old = Some(*x);
-                *x = c
-            })
-            .or_insert(c);
+    /// Moves the cursor off the right half of a wide character, past it when moving right and
+    /// onto its left half otherwise.
+    fn snap_cursor(&mut self, direction: Direction) {
+        if self.cell_at(self.cursor_absolute_position) == Some(&Cell::Continuation) {
+            self.cursor_absolute_position.x += match direction {
+                Direction::Right => 1,
+                _ => -1,
+            };
+        }
+    }
 
-        self.history.add(Change::AddedChar(
-            self.cursor_absolute_position,
-            c,
-            old.unwrap_or(' '),
-        ));
+    /// Sets the content of a single cell, recording the change.
+    fn set_cell(&mut self, position: Coordinates, cell: Option<Cell>) {
+        let old = match cell {
+            Some(ref cell) => self.text_storage.characters.insert(position, cell.clone()),
+            None => self.text_storage.characters.remove(&position),
+        };
+
+        match (cell, old) {
+            (Some(cell), old) if old.as_ref() != Some(&cell) => {
+                self.history.add(Change::AddedCell(position, cell, old))
+            }
+            (None, Some(old)) => self.history.add(Change::RemovedCell(position, old)),
+            _ => {}
+        }
 
         self.view_cache = None;
     }
 
+    /// Empties a cell, along with the other half of the wide character it is part of, if any.
+    fn clear_cell(&mut self, position: Coordinates) {
+        let right = position + (1, 0).into();
+        let left = position - (1, 0).into();
+        self.history.begin_group();
+
+        match self.cell_at(position) {
+            Some(Cell::Continuation) if self.cell_at(left).is_some_and(|c| c.width() == 2) => {
+                self.set_cell(left, None);
+            }
+            Some(cell) if cell.width() == 2 && self.cell_at(right) == Some(&Cell::Continuation) => {
+                self.set_cell(right, None);
+            }
+            _ => {}
+        }
+
+        self.set_cell(position, None);
+        self.history.end_group();
+    }
+
+    pub fn write_at(&mut self, position: Coordinates, c: char) {
+        self.write_grapheme_at(position, c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Writes a single grapheme cluster, which takes the cell to the right of `position` as well
+    /// if it is wide.
+    pub fn write_grapheme_at(&mut self, position: Coordinates, grapheme: &str) {
+        let cell = Cell::Grapheme(grapheme.to_string());
+
+        if self.cell_at(position) == Some(&cell) {
+            return;
+        }
+
+        let width = cell.width();
+        self.history.begin_group();
+
+        for offset in 0..width {
+            self.clear_cell(position + (offset as isize, 0).into());
+        }
+
+        self.set_cell(position, Some(cell));
+
+        if width == 2 {
+            self.set_cell(position + (1, 0).into(), Some(Cell::Continuation));
+        }
+
+        self.history.end_group();
+    }
+
+    /// Writes `s` from `position` onwards, wide characters taking two cells and combining marks
+    /// being added to the character on their left. Returns the position following the text.
+    pub fn write_str_at(&mut self, mut position: Coordinates, s: &str) -> Coordinates {
+        for grapheme in s.graphemes(true) {
+            if grapheme.chars().all(|c| c.width() == Some(0)) {
+                let mut base = position - (1, 0).into();
+
+                if self.cell_at(base) == Some(&Cell::Continuation) {
+                    base -= (1, 0).into();
+                }
+
+                if let Some(Cell::Grapheme(g)) = self.cell_at(base) {
+                    let combined = format!("{g}{grapheme}");
+                    self.set_cell(base, Some(Cell::Grapheme(combined)));
+                }
+
+                continue;
+            }
+
+            self.write_grapheme_at(position, grapheme);
+            position += (grapheme_width(grapheme) as isize, 0).into();
+        }
+
+        position
+    }
+
+    /// Erases the character at `position`, both halves of it if it is wide.
+    pub fn erase_at(&mut self, position: Coordinates) {
+        self.clear_cell(position);
+    }
+
+    pub fn write_at_cursor(&mut self, c: char) {
+        self.write_at(self.cursor_absolute_position, c);
+    }
+
     pub fn erase_at_cursor(&mut self) {
-        let old_char = self
+        self.erase_at(self.cursor_absolute_position);
+    }
+
+    /// Moves every cell of row `y` from column `from` onwards by `amount` columns, along with the
+    /// wide character whose right half is at `from`, the ones it lands on being overwritten.
+    pub fn shift_row(&mut self, y: isize, from: isize, amount: isize) {
+        let from = if self.cell_at((from, y).into()) == Some(&Cell::Continuation) {
+            from - 1
+        } else {
+            from
+        };
+
+        let mut moved = self
+            .text_storage
+            .characters
+            .iter()
+            .filter(|(k, _)| k.y == y && k.x >= from)
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<_>>();
+
+        // Move the cells closest to the destination first so that none is overwritten before
+        // being moved.
+        moved.sort_by_key(|(k, _)| if amount > 0 { -k.x } else { k.x });
+
+        for (position, cell) in moved {
+            self.set_cell(position, None);
+            self.set_cell(position + (amount, 0).into(), Some(cell));
+        }
+    }
+
+    /// Moves every cell from row `from` onwards by `amount` rows, the ones they land on being
+    /// overwritten.
+    pub fn shift_rows(&mut self, from: isize, amount: isize) {
+        let mut moved = self
             .text_storage
             .characters
-            .remove(&self.cursor_absolute_position);
+            .iter()
+            .filter(|(k, _)| k.y >= from)
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<_>>();
 
-        if let Some(c) = old_char {
-            self.history
-                .add(Change::RemovedChar(self.cursor_absolute_position, c));
+        moved.sort_by_key(|(k, _)| if amount > 0 { -k.y } else { k.y });
+
+        for (position, cell) in moved {
+            self.set_cell(position, None);
+            self.set_cell(position + (0, amount).into(), Some(cell));
         }
+    }
 
-        self.view_cache = None;
+    /// Moves every cell from column `from` onwards by `amount` columns, along with the wide
+    /// characters whose right half is in it.
+    pub fn shift_columns(&mut self, from: isize, amount: isize) {
+        let mut moved = self
+            .text_storage
+            .characters
+            .iter()
+            .filter(|(k, _)| {
+                k.x >= from
+                    || (k.x == from - 1
+                        && self.cell_at((from, k.y).into()) == Some(&Cell::Continuation))
+            })
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<_>>();
+
+        moved.sort_by_key(|(k, _)| if amount > 0 { -k.x } else { k.x });
+
+        for (position, cell) in moved {
+            self.set_cell(position, None);
+            self.set_cell(position + (amount, 0).into(), Some(cell));
+        }
+    }
+
+    /// Starts merging changes into a single undo step, until the matching call to
+    /// [`TextArea::end_undo_group`]. Groups can be nested.
+    pub fn begin_undo_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    pub fn cell_at(&self, position: Coordinates) -> Option<&Cell> {
+        self.text_storage.characters.get(&position)
+    }
+
+    /// The base character at `position`, which is `None` on the right half of wide characters.
+    pub fn char_at(&self, position: Coordinates) -> Option<char> {
+        self.grapheme_at(position).and_then(|g| g.chars().next())
+    }
+
+    /// The grapheme cluster starting at `position`.
+    pub fn grapheme_at(&self, position: Coordinates) -> Option<&str> {
+        match self.cell_at(position) {
+            Some(Cell::Grapheme(g)) => Some(g),
+            _ => None,
+        }
+    }
+
+    /// How many columns the character at `position` spans, 1 for empty cells.
+    pub fn width_at(&self, position: Coordinates) -> usize {
+        self.cell_at(position).map_or(1, |c| c.width().max(1))
     }
 
+    /// The graphemes from `start` up to the first empty cell on its right.
     pub fn string_at(&self, mut start: Coordinates) -> String {
         let mut s = String::new();
 
-        while let Some(c) = self.text_storage.characters.get(&start) {
-            s.push(*c);
+        while let Some(cell) = self.text_storage.characters.get(&start) {
+            if let Cell::Grapheme(g) = cell {
+                s.push_str(g);
+            }
+
             start += (1, 0).into();
         }
 
         s
     }
 
+    /// Whether nothing but a space is drawn at `position`.
+    pub fn is_blank(&self, position: &Coordinates) -> bool {
+        match self.text_storage.characters.get(position) {
+            Some(Cell::Grapheme(g)) => g == " ",
+            Some(Cell::Continuation) => false,
+            None => true,
+        }
+    }
+
+    /// The top left cell of every group of touching non-blank characters, each group being keyed
+    /// by all of its cells.
+    fn regions(&self) -> HashMap<Coordinates, Coordinates> {
+        let mut anchors = HashMap::new();
+
+        for start in self.text_storage.characters.keys() {
+            if self.is_blank(start) || anchors.contains_key(start) {
+                continue;
+            }
+
+            let mut cells = vec![*start];
+            let mut queue = VecDeque::from([*start]);
+            anchors.insert(*start, *start);
+
+            while let Some(cell) = queue.pop_front() {
+                for (dx, dy) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy))) {
+                    let neighbour = cell + (dx, dy).into();
+
+                    if !anchors.contains_key(&neighbour) && !self.is_blank(&neighbour) {
+                        anchors.insert(neighbour, *start);
+                        cells.push(neighbour);
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+
+            let anchor = *cells.iter().min_by_key(|c| (c.y, c.x)).unwrap();
+
+            for cell in cells {
+                anchors.insert(cell, anchor);
+            }
+        }
+
+        anchors
+    }
+
+    /// The top left cell of the next group of touching non-blank characters in reading order, or
+    /// of the previous one if `forward` isn't set. Wraps around at both ends.
+    pub fn region_from_cursor(&self, forward: bool) -> Option<Coordinates> {
+        let regions = self.regions();
+        let current = regions
+            .get(&self.cursor_absolute_position)
+            .copied()
+            .unwrap_or(self.cursor_absolute_position);
+
+        let mut anchors = regions.into_values().collect::<Vec<_>>();
+        anchors.sort_by_key(|c| (c.y, c.x));
+        anchors.dedup();
+
+        let key = |c: &Coordinates| (c.y, c.x);
+
+        if forward {
+            anchors
+                .iter()
+                .find(|a| key(a) > key(&current))
+                .or(anchors.first())
+                .copied()
+        } else {
+            anchors
+                .iter()
+                .rev()
+                .find(|a| key(a) < key(&current))
+                .or(anchors.last())
+                .copied()
+        }
+    }
+
+    /// Every grapheme of the area, including the ones outside of the view.
+    pub fn graphemes(&self) -> impl Iterator<Item = (Coordinates, &str)> + '_ {
+        self.text_storage
+            .characters
+            .iter()
+            .filter_map(|(k, v)| match v {
+                Cell::Grapheme(g) => Some((*k, g.as_str())),
+                Cell::Continuation => None,
+            })
+    }
+
+    pub fn objects(&self) -> &Objects {
+        &self.objects
+    }
+
+    /// Replaces the object layer, without drawing it, see [`crate::objects::update`].
+    pub fn set_objects(&mut self, objects: Objects) {
+        if objects != self.objects {
+            let previous = std::mem::replace(&mut self.objects, objects.clone());
+            self.history.add(Change::ReplacedObjects(
+                Box::new(previous),
+                Box::new(objects),
+            ));
+        }
+    }
+
+    /// Replaces the whole content of the area, forgetting its history.
+    pub fn load<I, S>(&mut self, graphemes: I, objects: Objects)
+    where
+        I: IntoIterator<Item = (Coordinates, S)>,
+        S: AsRef<str>,
+    {
+        self.text_storage.characters.clear();
+
+        for (position, grapheme) in graphemes {
+            let grapheme = grapheme.as_ref();
+            self.text_storage
+                .characters
+                .insert(position, Cell::Grapheme(grapheme.to_string()));
+
+            if grapheme_width(grapheme) == 2 {
+                self.text_storage
+                    .characters
+                    .insert(position + (1, 0).into(), Cell::Continuation);
+            }
+        }
+
+        self.objects = objects;
+        self.history = History::new();
+        self.view_cache = None;
+    }
+
     pub fn clear(&mut self) {
         self.text_storage.characters.clear();
         self.view_cache = None;
     }
 
     pub fn write_string_at_cursor(&mut self, s: &str) {
-        for c in s.chars() {
-            self.write_at_cursor(c);
-            self.move_cursor(Direction::Right);
-        }
+        let end = self.write_str_at(self.cursor_absolute_position, s);
+        self.move_cursor_to(end);
     }
 
     pub fn reset_cursor(&mut self) {
         self.cursor_absolute_position = (0, 0).into();
     }
 
-    pub fn replace_with_string(&mut self, s: &str) {
-        self.clear();
-        self.reset_cursor();
-        self.write_string_at_cursor(s);
-    }
-
     pub fn set_size(&mut self, width: usize, height: usize) {
         self.bounding_box.width = width;
         self.bounding_box.height = height;
         self.view_cache = None;
     }
 
+    /// Whether the area was changed since it was last saved, or loaded.
+    pub fn is_modified(&self) -> bool {
+        self.history.is_modified()
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.history.mark_saved();
+    }
+
+    /// Makes the area count as modified until it is saved, whatever is undone.
+    pub fn mark_modified(&mut self) {
+        self.history.mark_modified();
+    }
+
     pub fn undo(&mut self) {
-        if let Some(change) = self.history.undo() {
-            match change {
-                Change::AddedChar(pos, _, old_char) => {
-                    self.text_storage.characters.insert(*pos, *old_char);
-                    self.view_cache = None;
-                }
-                Change::RemovedChar(pos, c) => {
-                    self.text_storage
-                        .characters
-                        .entry(*pos)
-                        .and_modify(|x| *x = *c)
-                        .or_insert(*c);
-
-                    self.view_cache = None;
+        if let Some(changes) = self.history.undo() {
+            for change in changes.iter().rev() {
+                match change {
+                    Change::AddedCell(pos, _, Some(old)) => {
+                        self.text_storage.characters.insert(*pos, old.clone());
+                    }
+                    Change::AddedCell(pos, _, None) => {
+                        self.text_storage.characters.remove(pos);
+                    }
+                    Change::RemovedCell(pos, cell) => {
+                        self.text_storage.characters.insert(*pos, cell.clone());
+                    }
+                    Change::ReplacedObjects(previous, _) => self.objects = (**previous).clone(),
                 }
             }
+
+            self.view_cache = None;
         }
     }
 
     pub fn redo(&mut self) {
-        if let Some(change) = self.history.redo() {
-            match change {
-                Change::AddedChar(pos, c, _) => {
-                    self.text_storage
-                        .characters
-                        .entry(*pos)
-                        .and_modify(|x| *x = *c)
-                        .or_insert(*c);
-
-                    self.view_cache = None;
-                }
-                Change::RemovedChar(pos, _) => {
-                    self.text_storage.characters.remove(pos);
-                    self.view_cache = None;
+        if let Some(changes) = self.history.redo() {
+            for change in changes {
+                match change {
+                    Change::AddedCell(pos, cell, _) => {
+                        self.text_storage.characters.insert(*pos, cell.clone());
+                    }
+                    Change::RemovedCell(pos, _) => {
+                        self.text_storage.characters.remove(pos);
+                    }
+                    Change::ReplacedObjects(_, objects) => self.objects = (**objects).clone(),
                 }
             }
+
+            self.view_cache = None;
         }
     }
 }
@@ -0,0 +1,44 @@
+use crate::canvas::Color;
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub foreground: Color,
+    pub cursor: Color,
+    pub cursor_text: Color,
+    pub selection: Color,
+    pub status_background: Color,
+    pub status_foreground: Color,
+    pub grid: Color,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            background: Color::BLACK,
+            foreground: Color::WHITE,
+            cursor: Color::WHITE,
+            cursor_text: Color::BLACK,
+            selection: Color::new(0x26, 0x4f, 0x78),
+            status_background: Color::new(0x20, 0x20, 0x20),
+            status_foreground: Color::new(0xc0, 0xc0, 0xc0),
+            grid: Color::new(0x18, 0x18, 0x18),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            background: Color::WHITE,
+            foreground: Color::new(0x20, 0x20, 0x20),
+            cursor: Color::new(0x20, 0x20, 0x20),
+            cursor_text: Color::WHITE,
+            selection: Color::new(0xad, 0xd6, 0xff),
+            status_background: Color::new(0xe8, 0xe8, 0xe8),
+            status_foreground: Color::new(0x40, 0x40, 0x40),
+            grid: Color::new(0xf0, 0xf0, 0xf0),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Flips and quarter turns of a rectangle of the drawing, remapping line glyphs, arrowheads and
+//! diagonals so that they still join up once moved.
+
+use crate::{
+    glyphs::{self, Arrowhead, Connections},
+    text_area::{self, BoundingBox, Coordinates, Direction, TextArea},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Mirrors left and right.
+    FlipHorizontal,
+    /// Mirrors top and bottom.
+    FlipVertical,
+    RotateClockwise,
+    RotateCounterclockwise,
+}
+
+impl Transform {
+    /// Where a line going towards `direction` goes once transformed.
+    fn direction(&self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (Transform::FlipHorizontal, Direction::Left | Direction::Right)
+            | (Transform::FlipVertical, Direction::Up | Direction::Down) => direction.opposite(),
+            (Transform::FlipHorizontal | Transform::FlipVertical, _) => direction,
+            (Transform::RotateClockwise, _) => Direction::ALL[(direction as usize + 1) % 4],
+            (Transform::RotateCounterclockwise, _) => Direction::ALL[(direction as usize + 3) % 4],
+        }
+    }
+
+    /// Where the cell at `position` of a `width` by `height` rectangle goes, relative to its top
+    /// left corner, `columns` being the width of what is drawn there.
+    fn position(
+        &self,
+        position: Coordinates,
+        columns: usize,
+        width: usize,
+        height: usize,
+    ) -> Coordinates {
+        let (width, height, columns) = (width as isize, height as isize, columns as isize);
+        let Coordinates { x, y } = position;
+
+        match self {
+            Transform::FlipHorizontal => (width - x - columns, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::RotateClockwise => (height - 1 - y, x),
+            Transform::RotateCounterclockwise => (y, width - 1 - x),
+        }
+        .into()
+    }
+
+    /// The rectangle `bounds` turns into, which keeps its top left corner.
+    pub fn bounds(&self, bounds: BoundingBox) -> BoundingBox {
+        match self {
+            Transform::FlipHorizontal | Transform::FlipVertical => bounds,
+            _ => BoundingBox::new(bounds.top_left, bounds.height, bounds.width),
+        }
+    }
+
+    /// The glyph `c` turns into, which is `c` itself for anything but lines.
+    fn glyph(&self, c: char) -> char {
+        if let Some((connections, style)) = glyphs::connections(c) {
+            let connections = Direction::ALL
+                .into_iter()
+                .filter(|d| connections & glyphs::towards(*d) != 0)
+                .fold(0 as Connections, |transformed, d| {
+                    transformed | glyphs::towards(self.direction(d))
+                });
+
+            return glyphs::glyph(connections, style);
+        }
+
+        for arrowhead in Arrowhead::ALL.into_iter().filter(|a| *a != Arrowhead::None) {
+            if let Some(d) = Direction::ALL
+                .into_iter()
+                .find(|d| arrowhead.glyph(*d) == c)
+            {
+                return arrowhead.glyph(self.direction(d));
+            }
+        }
+
+        let turned = matches!(
+            self,
+            Transform::RotateClockwise | Transform::RotateCounterclockwise
+        );
+
+        // Every transform turns one diagonal into the other
+        match c {
+            '╱' => '╲',
+            '╲' => '╱',
+            '/' => '\\',
+            '\\' => '/',
+            '-' if turned => '|',
+            '|' if turned => '-',
+            _ => c,
+        }
+    }
+}
+
+/// Whether the grapheme at `position` is text rather than part of a line. ASCII characters that
+/// can be either are taken for text when right next to a letter or digit.
+fn is_text(area: &TextArea, position: Coordinates) -> bool {
+    let mut chars = area.grapheme_at(position).unwrap_or_default().chars();
+    let Some(c) = chars.next().filter(|_| chars.next().is_none()) else {
+        return true;
+    };
+
+    if "-|+/\\<>^v".contains(c) {
+        return [-1, area.width_at(position) as isize].into_iter().any(|x| {
+            area.char_at(position + (x, 0).into())
+                .is_some_and(char::is_alphanumeric)
+        });
+    }
+
+    let arrowhead = Arrowhead::ALL
+        .into_iter()
+        .any(|a| Direction::ALL.into_iter().any(|d| a.glyph(d) == c));
+
+    !(arrowhead || glyphs::connections(c).is_some() || "╱╲".contains(c))
+}
+
+/// Transforms what is drawn in `bounds`, as a single undo step, returning the rectangle it ends
+/// up in. With `keep_text`, runs of text are moved as a whole and left readable, instead of
+/// being mirrored or turned along with the lines. Objects are left where they are.
+pub fn transform(
+    area: &mut TextArea,
+    bounds: BoundingBox,
+    transform: Transform,
+    keep_text: bool,
+) -> BoundingBox {
+    let mut graphemes = area
+        .graphemes()
+        .filter(|(position, _)| bounds.contains(position))
+        .map(|(position, g)| (position - bounds.top_left, g.to_string()))
+        .collect::<Vec<_>>();
+    graphemes.sort_by_key(|(position, _)| (position.y, position.x));
+
+    let text = graphemes
+        .iter()
+        .map(|(position, _)| is_text(area, *position + bounds.top_left))
+        .collect::<Vec<_>>();
+
+    let mut moved = Vec::new();
+    let mut i = 0;
+
+    while i < graphemes.len() {
+        let (position, grapheme) = &graphemes[i];
+
+        if !(keep_text && text[i]) {
+            let columns = text_area::grapheme_width(grapheme);
+            let glyph = match text[i] {
+                true => grapheme.clone(),
+                false => transform
+                    .glyph(grapheme.chars().next().unwrap())
+                    .to_string(),
+            };
+
+            moved.push((
+                transform.position(*position, columns, bounds.width, bounds.height),
+                glyph,
+            ));
+            i += 1;
+            continue;
+        }
+
+        // A run of text goes on until the end of its row or two blank cells in a row
+        let mut end = i + 1;
+        let mut next_x = position.x + text_area::grapheme_width(grapheme) as isize;
+
+        while let Some((p, g)) = graphemes.get(end) {
+            if !text[end] || p.y != position.y || p.x > next_x + 1 {
+                break;
+            }
+
+            next_x = p.x + text_area::grapheme_width(g) as isize;
+            end += 1;
+        }
+
+        let first = match transform {
+            Transform::FlipHorizontal => (bounds.width as isize - next_x, position.y).into(),
+            _ => transform.position(*position, 1, bounds.width, bounds.height),
+        };
+
+        for (p, g) in &graphemes[i..end] {
+            moved.push((first + (p.x - position.x, 0).into(), g.clone()));
+        }
+
+        i = end;
+    }
+
+    area.begin_undo_group();
+
+    for (position, _) in &graphemes {
+        area.erase_at(*position + bounds.top_left);
+    }
+
+    for (position, grapheme) in moved {
+        area.write_grapheme_at(position + bounds.top_left, &grapheme);
+    }
+
+    area.end_undo_group();
+
+    transform.bounds(bounds)
+}
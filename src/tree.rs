@@ -0,0 +1,285 @@
+//! Directory-style trees, drawn from indented text or a list of paths and read back from the grid.
+
+use crate::{
+    glyphs::{self, Connections, LineStyle},
+    text_area::{self, Coordinates, TextArea},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    pub children: Vec<Node>,
+}
+
+/// Builds the trees of a list of `(depth, name)` entries, in order.
+fn build(entries: &[(usize, String)]) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut i = 0;
+
+    while i < entries.len() {
+        let depth = entries[i].0;
+        let end = entries[i + 1..]
+            .iter()
+            .position(|(d, _)| *d <= depth)
+            .map_or(entries.len(), |n| i + 1 + n);
+
+        nodes.push(Node {
+            name: entries[i].1.clone(),
+            children: build(&entries[i + 1..end]),
+        });
+        i = end;
+    }
+
+    nodes
+}
+
+/// Reads one node per line, each being a child of the closest line above it that is indented
+/// less, tabs counting as 4 spaces.
+pub fn parse_indented(s: &str) -> Vec<Node> {
+    let mut indents: Vec<usize> = Vec::new();
+    let mut entries = Vec::new();
+
+    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+        let name = line.trim_start();
+        let indent = line[..line.len() - name.len()]
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum::<usize>();
+
+        while indents.last().is_some_and(|i| *i >= indent) {
+            indents.pop();
+        }
+
+        entries.push((indents.len(), name.trim_end().to_string()));
+        indents.push(indent);
+    }
+
+    build(&entries)
+}
+
+/// Reads one `/` separated path per line, paths sharing their leading components.
+pub fn parse_paths(s: &str) -> Vec<Node> {
+    let mut roots = Vec::new();
+
+    for line in s.lines() {
+        let mut nodes = &mut roots;
+
+        for component in line.trim().split('/').filter(|c| !c.is_empty()) {
+            let i = match nodes.iter().position(|n: &Node| n.name == component) {
+                Some(i) => i,
+                None => {
+                    nodes.push(Node {
+                        name: component.to_string(),
+                        children: Vec::new(),
+                    });
+                    nodes.len() - 1
+                }
+            };
+
+            nodes = &mut nodes[i].children;
+        }
+    }
+
+    roots
+}
+
+/// Reads `s` as a list of paths if none of its lines is indented and some have a `/`, and as
+/// indented text otherwise.
+pub fn parse(s: &str) -> Vec<Node> {
+    let indented = s
+        .lines()
+        .any(|l| l.starts_with(char::is_whitespace) && !l.trim().is_empty());
+
+    if !indented && s.contains('/') {
+        parse_paths(s)
+    } else {
+        parse_indented(s)
+    }
+}
+
+/// Writes the trees back as indented text, two spaces per level.
+pub fn to_indented(nodes: &[Node]) -> String {
+    fn write(nodes: &[Node], depth: usize, s: &mut String) {
+        for node in nodes {
+            *s += &format!("{}{}\n", "  ".repeat(depth), node.name);
+            write(&node.children, depth + 1, s);
+        }
+    }
+
+    let mut s = String::new();
+    write(nodes, 0, &mut s);
+    s
+}
+
+/// Lays the trees out as lines of text, the roots on the first column and each child under its
+/// parent, `branch` being the length of the horizontal line leading to it.
+pub fn lines(roots: &[Node], style: LineStyle, branch: usize) -> Vec<String> {
+    fn children(
+        nodes: &[Node],
+        prefix: &str,
+        style: LineStyle,
+        branch: usize,
+        lines: &mut Vec<String>,
+    ) {
+        let vertical = glyphs::glyph(glyphs::UP | glyphs::DOWN, style);
+        let horizontal = glyphs::glyph(glyphs::LEFT | glyphs::RIGHT, style);
+
+        for (i, node) in nodes.iter().enumerate() {
+            let last = i + 1 == nodes.len();
+            let (tee, continuation) = match last {
+                true => (glyphs::UP | glyphs::RIGHT, ' '),
+                false => (glyphs::UP | glyphs::RIGHT | glyphs::DOWN, vertical),
+            };
+
+            lines.push(format!(
+                "{prefix}{}{} {}",
+                glyphs::glyph(tee, style),
+                horizontal.to_string().repeat(branch),
+                node.name
+            ));
+
+            let prefix = format!("{prefix}{continuation}{}", " ".repeat(branch + 1));
+            children(&node.children, &prefix, style, branch, lines);
+        }
+    }
+
+    let mut lines = Vec::new();
+
+    for root in roots {
+        lines.push(root.name.clone());
+        children(&root.children, "", style, branch.max(1), &mut lines);
+    }
+
+    lines
+}
+
+/// Draws the trees with their first root at `position`, as a single undo step. Spaces are left
+/// out, so that the tree doesn't erase what is around its lines.
+pub fn draw(
+    area: &mut TextArea,
+    position: Coordinates,
+    roots: &[Node],
+    style: LineStyle,
+    branch: usize,
+) {
+    area.begin_undo_group();
+
+    for (y, line) in lines(roots, style, branch).iter().enumerate() {
+        for (x, grapheme) in text_area::lay_out(line).filter(|(_, g)| *g != " ") {
+            area.write_grapheme_at(position + (x, y as isize).into(), grapheme);
+        }
+    }
+
+    area.end_undo_group();
+}
+
+/// The connections of a tree glyph, ASCII trees being drawn with `|`, `+` and `-`.
+fn connections(area: &TextArea, position: Coordinates) -> Connections {
+    match area.char_at(position) {
+        Some('|') => glyphs::UP | glyphs::DOWN,
+        Some('+') => glyphs::UP | glyphs::RIGHT | glyphs::DOWN,
+        Some('-') => glyphs::LEFT | glyphs::RIGHT,
+        Some(c) => glyphs::connections(c).map_or(0, |(connections, _)| connections),
+        None => 0,
+    }
+}
+
+/// The name starting at `position`, which ends at the first two blank cells in a row.
+fn read_name(area: &TextArea, mut position: Coordinates) -> String {
+    let mut name = String::new();
+
+    while !(area.is_blank(&position) && area.is_blank(&(position + (1, 0).into()))) {
+        name.push_str(area.grapheme_at(position).unwrap_or(" "));
+        position += (area.width_at(position) as isize, 0).into();
+    }
+
+    name.trim_end().to_string()
+}
+
+/// Reads the children drawn below the row `y`, their branches starting in the column `x`.
+fn read_children(area: &TextArea, x: isize, y: isize) -> Vec<Node> {
+    let mut children = Vec::new();
+
+    for y in y + 1.. {
+        let position = (x, y).into();
+        let tee = connections(area, position);
+
+        if tee & glyphs::UP == 0 {
+            break;
+        }
+
+        if tee & glyphs::RIGHT != 0 {
+            let mut label = position + (1, 0).into();
+
+            while connections(area, label) & glyphs::LEFT != 0 {
+                label += (1, 0).into();
+            }
+            while area.is_blank(&label) && !area.is_blank(&(label + (1, 0).into())) {
+                label += (1, 0).into();
+            }
+
+            children.push(Node {
+                name: read_name(area, label),
+                children: read_children(area, label.x, y),
+            });
+        }
+
+        if tee & glyphs::DOWN == 0 {
+            break;
+        }
+    }
+
+    children
+}
+
+/// Reads the tree whose root name starts at `root` back from the grid.
+pub fn extract(area: &TextArea, root: Coordinates) -> Option<Node> {
+    let name = read_name(area, root);
+
+    (!name.is_empty()).then(|| Node {
+        name,
+        children: read_children(area, root.x, root.y),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_indented_text() {
+        for (s, indented) in [
+            ("", ""),
+            ("a\n  b\n  c\n    d\ne", "a\n  b\n  c\n    d\ne\n"),
+            ("a\n\tb\n    c\n", "a\n  b\n  c\n"),
+            ("a\n    b\n  c\n", "a\n  b\n  c\n"),
+            ("  a\nb\n", "a\nb\n"),
+            ("a  \n\n   \n b c\n", "a\n  b c\n"),
+        ] {
+            assert_eq!(to_indented(&parse_indented(s)), indented, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn extract_drawn_trees() {
+        let roots = parse_indented("root\n  a b\n    世界\n    d\n  e\n    f\n      g\n");
+
+        for (style, branch) in [
+            (LineStyle::Light, 2),
+            (LineStyle::Rounded, 1),
+            (LineStyle::Double, 3),
+            (LineStyle::Ascii, 2),
+        ] {
+            let mut area = TextArea::new(0, 0);
+            draw(&mut area, (3, 2).into(), &roots, style, branch);
+
+            assert_eq!(
+                extract(&area, (3, 2).into()).as_ref(),
+                roots.first(),
+                "{style} with branches of {branch}"
+            );
+        }
+
+        assert_eq!(extract(&TextArea::new(0, 0), (0, 0).into()), None);
+    }
+}